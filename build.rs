@@ -1,9 +1,14 @@
 // https://crates.io/crates/vergen
 extern crate vergen;
 
+mod codegen;
+
 use vergen::{generate_cargo_keys, ConstantsFlags};
 
 fn main() {
     // Generate the 'cargo:' key output
     generate_cargo_keys(ConstantsFlags::all()).expect("Unable to generate the cargo keys!");
+
+    // Kodi's JSON-RPC API for src/kodi_api.rs
+    codegen::generate("schema/introspect.json", "kodi_api.rs");
 }
@@ -0,0 +1,606 @@
+// Generates Rust types and async method wrappers from a JSONRPC.Introspect dump of Kodi's
+// API. Used by build.rs; the output is included by src/kodi_api.rs.
+//
+// Each schema type becomes a Rust type named after its id without the dots
+// (Player.Position.Time becomes PlayerPositionTime):
+// - objects become structs; properties that aren't required are Options, and
+//   properties of the types they extend are copied in
+// - string enums become enums
+// - unions ("type": [...]) become untagged enums
+// - objects with both properties and additionalProperties also get the rest in a map
+// - everything else becomes a type alias, e.g. "any" and tuples ("items": [...]) become
+//   serde_json::Value
+// Types defined inline get their names from where they are, e.g. the parameters of
+// Player.Seek are PlayerSeekParams and its "value" is PlayerSeekParamsValue.
+//
+// Each method becomes an async fn named in snake case (Player.Seek becomes player_seek)
+// taking the parameters as one struct, if it has any. The parameters of each
+// notification become a struct too, e.g. PlayerOnPlayParams.
+//
+// The dump can be the result of JSONRPC.Introspect or the whole response.
+
+use serde_json::{Map, Value};
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+const KEYWORDS: &[&str] = &[
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "override", "priv", "pub", "ref", "return", "self", "static", "struct", "super",
+    "trait", "true", "type", "unsafe", "use", "where", "while", "yield",
+];
+
+pub fn generate(schema_path: &str, output_name: &str) {
+    println!("cargo:rerun-if-changed={}", schema_path);
+    println!("cargo:rerun-if-changed=codegen");
+    let schema = std::fs::read_to_string(schema_path)
+        .unwrap_or_else(|err| panic!("Cannot read {}: {}", schema_path, err));
+    let schema: Value = serde_json::from_str(&schema)
+        .unwrap_or_else(|err| panic!("Cannot parse {}: {}", schema_path, err));
+    let schema = match schema.get("result") {
+        Some(result) => result.clone(),
+        None => schema,
+    };
+    let code = Generator::new(&schema).run();
+    let output =
+        PathBuf::from(std::env::var("OUT_DIR").expect("OUT_DIR is not set")).join(output_name);
+    std::fs::write(&output, code)
+        .unwrap_or_else(|err| panic!("Cannot write {}: {}", output.to_string_lossy(), err));
+}
+
+// "Player.Position.Time" -> "PlayerPositionTime", "smallforward" -> "Smallforward"
+fn camel_case(name: &str) -> String {
+    let mut result = String::new();
+    for part in name.split(|char: char| !char.is_ascii_alphanumeric()) {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            result.push(first.to_ascii_uppercase());
+            result.extend(chars);
+        }
+    }
+    match result.chars().next() {
+        None => String::from("Empty"),
+        Some(first) if first.is_ascii_digit() => format!("V{}", result),
+        Some(_) => result,
+    }
+}
+
+// "GetInfoLabels" -> "get_info_labels", "JSONRPC" -> "jsonrpc"
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut result = String::new();
+    for (index, char) in chars.iter().enumerate() {
+        if !char.is_ascii_alphanumeric() {
+            if !result.is_empty() && !result.ends_with('_') {
+                result.push('_');
+            }
+            continue;
+        }
+        if char.is_ascii_uppercase() && index > 0 && !result.ends_with('_') {
+            let previous = chars[index - 1];
+            let next_lower = chars
+                .get(index + 1)
+                .map_or(false, |x| x.is_ascii_lowercase());
+            if previous.is_ascii_lowercase()
+                || previous.is_ascii_digit()
+                || (previous.is_ascii_uppercase() && next_lower)
+            {
+                result.push('_');
+            }
+        }
+        result.push(char.to_ascii_lowercase());
+    }
+    if result.is_empty() || result.starts_with(|char: char| char.is_ascii_digit()) {
+        result.insert(0, '_');
+    }
+    if KEYWORDS.contains(&result.as_str()) {
+        result.push('_');
+    }
+    result
+}
+
+// indentation is dropped so that rustdoc doesn't take any of it for a code block
+fn doc_comment(out: &mut String, indent: &str, schema: &Value) {
+    if let Some(description) = schema.get("description").and_then(|x| x.as_str()) {
+        for line in description.lines() {
+            let _ = writeln!(out, "{}/// {}", indent, line.trim());
+        }
+    }
+}
+
+// plain strings are shorthands for {"type": ...}, e.g. "returns": "string"
+fn expand(schema: &Value) -> Value {
+    match schema {
+        Value::String(type_) => serde_json::json!({ "type": type_ }),
+        schema => schema.clone(),
+    }
+}
+
+// "type" as a list of schemas
+fn union_members(types: &[Value]) -> Vec<Value> {
+    types.iter().map(expand).collect()
+}
+
+// "1.2.3" for both "version": "1.2.3" and "version": {"major": 1, "minor": 2, "patch": 3}
+fn version(schema: &Value) -> String {
+    match schema.get("version") {
+        Some(Value::String(version)) => version.clone(),
+        Some(Value::Object(parts)) => ["major", "minor", "patch"]
+            .iter()
+            .filter_map(|part| parts.get(*part).map(|x| x.to_string()))
+            .collect::<Vec<String>>()
+            .join("."),
+        Some(version) => version.to_string(),
+        None => String::new(),
+    }
+}
+
+fn extends(schema: &Value) -> Vec<String> {
+    match schema.get("extends") {
+        Some(Value::String(id)) => vec![id.clone()],
+        Some(Value::Array(ids)) => ids
+            .iter()
+            .filter_map(|id| id.as_str().map(String::from))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn is_required(schema: &Value) -> bool {
+    schema.get("required").and_then(|x| x.as_bool()) == Some(true)
+}
+
+struct Generator<'a> {
+    schema: &'a Value,
+    types: Map<String, Value>,
+    out: String,
+    // Rust names in use, so that inline types don't collide with anything
+    names: HashSet<String>,
+    // Rust names of the schema types, unique even if two ids only differ in punctuation
+    type_names: HashMap<String, String>,
+    functions: HashSet<String>,
+    generated: HashSet<String>,
+    // names of inline types by where they are in the schema
+    inline: HashMap<String, String>,
+    // schema types being generated; referring to one of these is recursion and needs a Box
+    in_progress: Vec<String>,
+}
+
+impl<'a> Generator<'a> {
+    fn new(schema: &'a Value) -> Generator<'a> {
+        let types = schema
+            .get("types")
+            .and_then(|x| x.as_object())
+            .cloned()
+            .unwrap_or_default();
+        let mut generator = Generator {
+            schema,
+            types: types.clone(),
+            out: String::new(),
+            names: HashSet::new(),
+            type_names: HashMap::new(),
+            functions: HashSet::new(),
+            generated: HashSet::new(),
+            inline: HashMap::new(),
+            in_progress: Vec::new(),
+        };
+        for id in types.keys() {
+            let name = generator.unique_name(&camel_case(id));
+            generator.type_names.insert(id.clone(), name);
+        }
+        generator
+    }
+
+    fn type_name(&self, id: &str) -> String {
+        match self.type_names.get(id) {
+            Some(name) => name.clone(),
+            None => panic!("Unknown type {}", id),
+        }
+    }
+
+    fn run(mut self) -> String {
+        let _ = writeln!(
+            self.out,
+            "// Generated by codegen/mod.rs from Kodi's JSON-RPC API schema; do not edit.\n"
+        );
+        let _ = writeln!(
+            self.out,
+            "// JSON-RPC API version of the schema\npub const SCHEMA_VERSION: &str = {:?};\n",
+            version(self.schema)
+        );
+        let ids: Vec<String> = self.types.keys().cloned().collect();
+        for id in ids {
+            self.named_type(&id);
+        }
+        let methods = self
+            .schema
+            .get("methods")
+            .and_then(|x| x.as_object())
+            .cloned()
+            .unwrap_or_default();
+        for (name, method) in methods.iter() {
+            self.method(name, method);
+        }
+        let notifications = self
+            .schema
+            .get("notifications")
+            .and_then(|x| x.as_object())
+            .cloned()
+            .unwrap_or_default();
+        for (name, notification) in notifications.iter() {
+            self.params_type(&camel_case(name), notification);
+        }
+        self.out
+    }
+
+    fn unique_name(&mut self, hint: &str) -> String {
+        let mut name = String::from(hint);
+        let mut counter = 2;
+        while self.names.contains(&name) {
+            name = format!("{}{}", hint, counter);
+            counter += 1;
+        }
+        self.names.insert(name.clone());
+        name
+    }
+
+    // defines an inline type once; the same hint is the same place in the schema, e.g. a
+    // property copied into each type extending the one that has it
+    fn inline_type(&mut self, hint: &str, schema: &Value) -> String {
+        if let Some(name) = self.inline.get(hint) {
+            return name.clone();
+        }
+        let name = self.unique_name(hint);
+        self.inline.insert(String::from(hint), name.clone());
+        self.definition(&name, schema);
+        name
+    }
+
+    fn named_type(&mut self, id: &str) {
+        if self.generated.contains(id) || self.in_progress.iter().any(|x| x == id) {
+            return;
+        }
+        let schema = match self.types.get(id) {
+            Some(schema) => schema.clone(),
+            None => panic!("Unknown type {}", id),
+        };
+        self.in_progress.push(String::from(id));
+        let name = self.type_name(id);
+        self.definition(&name, &schema);
+        self.in_progress.pop();
+        self.generated.insert(String::from(id));
+    }
+
+    // the "type" of a schema, following $ref and extends
+    fn kind(&self, schema: &Value) -> Option<Value> {
+        if let Some(type_) = schema.get("type") {
+            return Some(type_.clone());
+        }
+        if let Value::String(type_) = schema {
+            return Some(Value::String(type_.clone()));
+        }
+        if let Some(id) = schema.get("$ref").and_then(|x| x.as_str()) {
+            return self.types.get(id).and_then(|x| self.kind(x));
+        }
+        extends(schema)
+            .first()
+            .and_then(|id| self.types.get(id))
+            .and_then(|x| self.kind(x))
+    }
+
+    // enum values of a string type, including those of the types it extends
+    fn enum_values(&self, schema: &Value) -> Vec<String> {
+        let mut values = Vec::new();
+        for id in extends(schema) {
+            if let Some(parent) = self.types.get(&id) {
+                values.extend(self.enum_values(parent));
+            }
+        }
+        if let Some(own) = schema.get("enum").and_then(|x| x.as_array()) {
+            values.extend(own.iter().filter_map(|x| x.as_str().map(String::from)));
+        }
+        let mut seen = HashSet::new();
+        values.retain(|value| seen.insert(value.clone()));
+        values
+    }
+
+    // properties of an object type, including those of the types it extends, along with
+    // the name of the type each is from
+    fn properties(&self, schema: &Value, owner: &str) -> Vec<(String, Value, String)> {
+        let mut properties: Vec<(String, Value, String)> = Vec::new();
+        let mut add = |name: &String, property: &Value, owner: &str| {
+            let entry = (name.clone(), property.clone(), String::from(owner));
+            match properties
+                .iter_mut()
+                .find(|(existing, _, _)| existing == name)
+            {
+                Some(existing) => *existing = entry,
+                None => properties.push(entry),
+            }
+        };
+        for id in extends(schema) {
+            if let Some(parent) = self.types.get(&id) {
+                for (name, property, owner) in self.properties(parent, &self.type_name(&id)) {
+                    add(&name, &property, &owner);
+                }
+            }
+        }
+        if let Some(own) = schema.get("properties").and_then(|x| x.as_object()) {
+            for (name, property) in own {
+                add(name, property, owner);
+            }
+        }
+        properties
+    }
+
+    // the schema for additionalProperties, if the object type or one it extends has one
+    fn additional_properties(&self, schema: &Value) -> Option<Value> {
+        match schema.get("additionalProperties") {
+            Some(values @ Value::Object(_)) | Some(values @ Value::String(_)) => {
+                Some(expand(values))
+            }
+            Some(_) => None,
+            None => extends(schema)
+                .iter()
+                .filter_map(|id| self.types.get(id))
+                .find_map(|x| self.additional_properties(x)),
+        }
+    }
+
+    // defines a type with the given name for the schema
+    fn definition(&mut self, name: &str, schema: &Value) {
+        let schema = &expand(schema);
+        let mut out = String::new();
+        doc_comment(&mut out, "", schema);
+        match self.kind(schema) {
+            Some(Value::Array(members)) if schema.get("$ref").is_none() => {
+                self.union(&mut out, name, &union_members(&members))
+            }
+            Some(Value::String(type_))
+                if type_ == "object"
+                    && schema.get("$ref").is_none()
+                    && !self.properties(schema, "").is_empty() =>
+            {
+                self.struct_(&mut out, name, schema)
+            }
+            Some(Value::String(type_))
+                if type_ == "string"
+                    && schema.get("$ref").is_none()
+                    && !self.enum_values(schema).is_empty() =>
+            {
+                self.string_enum(&mut out, name, schema)
+            }
+            _ => {
+                // an alias can't be recursive, so it never needs a Box
+                let target = self.type_expr(schema, name, false);
+                let _ = writeln!(out, "pub type {} = {};\n", name, target);
+            }
+        }
+        self.out.push_str(&out);
+    }
+
+    fn struct_(&mut self, out: &mut String, name: &str, schema: &Value) {
+        let mut fields = String::new();
+        let mut used = HashSet::new();
+        for (json_name, property, owner) in self.properties(schema, name) {
+            let mut field = snake_case(&json_name);
+            while !used.insert(field.clone()) {
+                field.push('_');
+            }
+            let type_ = self.type_expr(
+                &property,
+                &format!("{}{}", owner, camel_case(&json_name)),
+                true,
+            );
+            doc_comment(&mut fields, "    ", &property);
+            let rename = if field != json_name {
+                format!("rename = {:?}, ", json_name)
+            } else {
+                String::new()
+            };
+            if is_required(&property) {
+                if !rename.is_empty() {
+                    let _ = writeln!(fields, "    #[serde({})]", rename.trim_end_matches(", "));
+                }
+                let _ = writeln!(fields, "    pub {}: {},", field, type_);
+            } else {
+                let _ = writeln!(
+                    fields,
+                    "    #[serde({}default, skip_serializing_if = \"Option::is_none\")]",
+                    rename
+                );
+                let _ = writeln!(fields, "    pub {}: Option<{}>,", field, type_);
+            }
+        }
+        if let Some(values) = self.additional_properties(schema) {
+            let mut field = String::from("other");
+            while !used.insert(field.clone()) {
+                field.push('_');
+            }
+            let type_ = self.type_expr(&values, &format!("{}Other", name), false);
+            let _ = writeln!(
+                fields,
+                "    #[serde(flatten)]\n    pub {}: std::collections::HashMap<String, {}>,",
+                field, type_
+            );
+        }
+        let _ = writeln!(
+            out,
+            "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\npub struct {} {{\n{}}}\n",
+            name, fields
+        );
+    }
+
+    fn string_enum(&mut self, out: &mut String, name: &str, schema: &Value) {
+        let mut variants = String::new();
+        let mut used = HashSet::new();
+        for value in self.enum_values(schema) {
+            let mut variant = camel_case(&value);
+            let mut counter = 2;
+            while !used.insert(variant.clone()) {
+                variant = format!("{}{}", camel_case(&value), counter);
+                counter += 1;
+            }
+            let _ = writeln!(
+                variants,
+                "    #[serde(rename = {:?})]\n    {},",
+                value, variant
+            );
+        }
+        let _ = writeln!(
+            out,
+            "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]\npub enum {} {{\n{}}}\n",
+            name, variants
+        );
+    }
+
+    fn union(&mut self, out: &mut String, name: &str, members: &[Value]) {
+        let mut variants = String::new();
+        let mut used = HashSet::new();
+        for member in members {
+            let base = match (
+                member.get("$ref").and_then(|x| x.as_str()),
+                self.kind(member),
+            ) {
+                (Some(id), _) => camel_case(id),
+                // objects are told apart by their properties, e.g. {"time": ...} is Time
+                (None, Some(Value::String(type_))) if type_ == "object" => self
+                    .properties(member, "")
+                    .iter()
+                    .find(|(_, property, _)| is_required(property))
+                    .map_or(String::from("Object"), |(name, _, _)| camel_case(name)),
+                (None, Some(Value::String(type_))) => camel_case(&type_),
+                (None, _) => String::from("Any"),
+            };
+            let mut variant = base.clone();
+            let mut counter = 2;
+            while !used.insert(variant.clone()) {
+                variant = format!("{}{}", base, counter);
+                counter += 1;
+            }
+            doc_comment(&mut variants, "    ", member);
+            if member.get("type").and_then(|x| x.as_str()) == Some("null") {
+                // untagged, a unit variant is null
+                let _ = writeln!(variants, "    {},", variant);
+            } else {
+                let type_ = self.type_expr(member, &format!("{}{}", name, variant), true);
+                let _ = writeln!(variants, "    {}({}),", variant, type_);
+            }
+        }
+        let _ = writeln!(
+            out,
+            "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\n#[serde(untagged)]\npub enum {} {{\n{}}}\n",
+            name, variants
+        );
+    }
+
+    // the Rust type for a schema, defining inline types named after the hint as needed;
+    // direct is false inside containers, where recursion doesn't need a Box
+    fn type_expr(&mut self, schema: &Value, hint: &str, direct: bool) -> String {
+        let schema = &expand(schema);
+        if let Some(id) = schema.get("$ref").and_then(|x| x.as_str()) {
+            let id = String::from(id);
+            let recursive = self.in_progress.contains(&id);
+            self.named_type(&id);
+            let name = self.type_name(&id);
+            return if recursive && direct {
+                format!("Box<{}>", name)
+            } else {
+                name
+            };
+        }
+        let kind = self.kind(schema);
+        match kind {
+            Some(Value::Array(_)) => self.inline_type(hint, schema),
+            Some(Value::String(type_)) => match type_.as_str() {
+                "string" if !self.enum_values(schema).is_empty() => self.inline_type(hint, schema),
+                "string" => String::from("String"),
+                "integer" => String::from("i64"),
+                "number" => String::from("f64"),
+                "boolean" => String::from("bool"),
+                "null" => String::from("()"),
+                "array" => {
+                    let items = schema.get("items").cloned().or_else(|| {
+                        extends(schema)
+                            .first()
+                            .and_then(|id| self.types.get(id))
+                            .and_then(|x| x.get("items").cloned())
+                    });
+                    match items {
+                        Some(items @ Value::Object(_)) | Some(items @ Value::String(_)) => {
+                            format!(
+                                "Vec<{}>",
+                                self.type_expr(&items, &format!("{}Item", hint), false)
+                            )
+                        }
+                        _ => String::from("Vec<serde_json::Value>"),
+                    }
+                }
+                "object" if !self.properties(schema, "").is_empty() => {
+                    self.inline_type(hint, schema)
+                }
+                "object" => match self.additional_properties(schema) {
+                    Some(values) => format!(
+                        "std::collections::HashMap<String, {}>",
+                        self.type_expr(&values, &format!("{}Value", hint), false)
+                    ),
+                    _ => String::from("serde_json::Map<String, serde_json::Value>"),
+                },
+                // "any" and anything newer
+                _ => String::from("serde_json::Value"),
+            },
+            _ => String::from("serde_json::Value"),
+        }
+    }
+
+    // the parameters of a method or notification as one struct, sent by name
+    fn params_type(&mut self, prefix: &str, method: &Value) -> Option<String> {
+        let mut properties = Map::new();
+        let params = method.get("params").and_then(|x| x.as_array());
+        for param in params.into_iter().flatten() {
+            if let Some(param_name) = param.get("name").and_then(|x| x.as_str()) {
+                properties.insert(String::from(param_name), param.clone());
+            }
+        }
+        if properties.is_empty() {
+            return None;
+        }
+        let schema = serde_json::json!({ "type": "object", "properties": properties });
+        Some(self.type_expr(&schema, &format!("{}Params", prefix), false))
+    }
+
+    fn method(&mut self, name: &str, method: &Value) {
+        let mut function = snake_case(name);
+        while !self.functions.insert(function.clone()) {
+            function.push('_');
+        }
+        let prefix = camel_case(name);
+        let params_type = self.params_type(&prefix, method);
+        let returns = match method.get("returns") {
+            Some(returns) => self.type_expr(returns, &format!("{}Returns", prefix), false),
+            None => String::from("serde_json::Value"),
+        };
+        let mut out = String::new();
+        let _ = writeln!(out, "// {}", name);
+        doc_comment(&mut out, "", method);
+        match params_type {
+            Some(params_type) => {
+                let _ = writeln!(
+                    out,
+                    "pub async fn {}(\n    session: &mut WsJsonRPCSession,\n    params: {},\n) -> Result<{}, Error> {{\n    request(session, {:?}, Some(params)).await\n}}\n",
+                    function, params_type, returns, name
+                );
+            }
+            None => {
+                let _ = writeln!(
+                    out,
+                    "pub async fn {}(session: &mut WsJsonRPCSession) -> Result<{}, Error> {{\n    request(session, {:?}, NO_PARAMS).await\n}}\n",
+                    function, returns, name
+                );
+            }
+        }
+        self.out.push_str(&out);
+    }
+}
@@ -0,0 +1,109 @@
+use kodi_kontrol::{mock_kodi, version::get_version};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    MockError(#[from] mock_kodi::Error),
+
+    #[error(transparent)]
+    ParseIntError(#[from] std::num::ParseIntError),
+
+    #[error(transparent)]
+    AddrParseError(#[from] std::net::AddrParseError),
+
+    #[error("Failed to set up logging: {}", .0)]
+    LoggingError(String),
+}
+
+fn init_logging(debug: bool) -> Result<(), Error> {
+    use log::LevelFilter;
+    use log4rs::append::console::{ConsoleAppender, Target};
+    use log4rs::config::{Appender, Config, Root};
+    use log4rs::encode::pattern::PatternEncoder;
+
+    let console = ConsoleAppender::builder()
+        .target(Target::Stderr)
+        .encoder(Box::new(PatternEncoder::new("{d} {l} {m}\n")))
+        .build();
+    let config = Config::builder()
+        .appender(Appender::builder().build("console", Box::new(console)))
+        .build(Root::builder().appender("console").build(if debug {
+            LevelFilter::Debug
+        } else {
+            LevelFilter::Info
+        }))
+        .map_err(|err| Error::LoggingError(err.to_string()))?;
+    log4rs::init_config(config).map_err(|err| Error::LoggingError(err.to_string()))?;
+    Ok(())
+}
+
+async fn actual_main() -> Result<(), Error> {
+    let args = clap::App::new("kodi-mock")
+        .version(get_version().as_str())
+        .author("Erkki Seppälä <erkki.seppala@vincit.fi>")
+        .about("Pretends to be Kodi, for trying out koko without one")
+        .arg(
+            clap::Arg::new("address")
+                .long("address")
+                .default_value("127.0.0.1")
+                .about("Address to listen on"),
+        )
+        .arg(
+            clap::Arg::new("port")
+                .long("port")
+                .default_value("8080")
+                .about("Port of the HTTP JSON-RPC, like Kodi's web server"),
+        )
+        .arg(
+            clap::Arg::new("ws_port")
+                .long("ws-port")
+                .default_value("9090")
+                .about("Port of the WebSocket JSON-RPC"),
+        )
+        .arg(
+            clap::Arg::new("item_duration")
+                .long("item-duration")
+                .default_value("30")
+                .about("Seconds each item plays"),
+        )
+        .arg(
+            clap::Arg::new("debug")
+                .long("debug")
+                .short('d')
+                .about("Log the requests"),
+        )
+        .get_matches();
+
+    init_logging(args.is_present("debug"))?;
+
+    let kodi = mock_kodi::MockKodi::start(mock_kodi::Options {
+        address: args.value_of("address").unwrap().parse()?,
+        http_port: args.value_of("port").unwrap().parse()?,
+        ws_port: args.value_of("ws_port").unwrap().parse()?,
+        item_duration: std::time::Duration::from_secs(
+            args.value_of("item_duration").unwrap().parse()?,
+        ),
+    })
+    .await?;
+    log::info!(
+        "Listening on {} (HTTP) and {} (WebSocket)",
+        kodi.http_addr(),
+        kodi.ws_addr()
+    );
+
+    let _ = tokio::signal::ctrl_c().await;
+    Ok(())
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    match actual_main().await {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            Ok(())
+        }
+    }
+}
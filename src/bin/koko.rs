@@ -1,9 +1,13 @@
-use kodi_kontrol::{config, exit, kodi_control, server, ui, util, version::get_version};
+use kodi_kontrol::{
+    config, doctor, exit, history, kodi_control, kodi_rpc, kodi_rpc_types, logging, media,
+    position::Position, server, ui, util, version::get_version, watch, wol,
+};
 
 use directories::ProjectDirs;
+use std::ffi::OsStr;
 use std::path::Path;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 use trust_dns_resolver::error::ResolveError;
@@ -39,6 +43,45 @@ pub enum Error {
 
     #[error(transparent)]
     ParseIntError(#[from] std::num::ParseIntError),
+
+    #[error(transparent)]
+    PositionError(#[from] kodi_kontrol::position::Error),
+
+    #[error(transparent)]
+    MediaError(#[from] media::Error),
+
+    #[error("Background audio can only be played with pictures")]
+    BackgroundAudioError,
+
+    #[error(transparent)]
+    KodiError(#[from] kodi_kontrol::error::Error),
+
+    #[error(transparent)]
+    UrlParseError(#[from] url::ParseError),
+
+    #[error(transparent)]
+    WakeError(#[from] wol::Error),
+
+    #[error("No MAC address configured for waking up the host")]
+    NoMacError,
+
+    #[error("Invalid JSON parameters: {}", .0)]
+    ParamsError(serde_json::Error),
+
+    #[error("Kodi returned an error: {}", .0)]
+    RpcError(String),
+
+    #[error(transparent)]
+    HistoryError(#[from] history::Error),
+
+    #[error(transparent)]
+    WatchError(#[from] watch::Error),
+
+    #[error("Cannot determine where the history is kept")]
+    NoHistoryError,
+
+    #[error("There is no session {} in the history", .0)]
+    NoSuchSessionError(usize),
 }
 
 async fn resolve_address(hostname_arg: Option<String>) -> Result<std::net::IpAddr, Error> {
@@ -61,90 +104,53 @@ async fn resolve_address(hostname_arg: Option<String>) -> Result<std::net::IpAdd
 #[derive(Error, Debug)]
 pub enum SetupError {
     #[error(transparent)]
-    InitLoggingError(#[from] log4rs::config::InitError),
-
-    #[error(transparent)]
-    LogFileOpenError(#[from] std::io::Error),
+    LoggingError(#[from] logging::Error),
 }
 
-fn init_logging(enable: bool) -> Result<(), SetupError> {
-    if !enable {
-        return Ok(());
-    }
+// the command line overrides the [logging] section; logging is off unless asked for
+fn init_logging(
+    args: &clap::ArgMatches,
+    config: Option<config::Logging>,
+) -> Result<(), SetupError> {
     use log::LevelFilter;
-    use log4rs::append::file::FileAppender;
-    use log4rs::config::{Appender, Config, Root};
-    use log4rs::encode::pattern::PatternEncoder;
-
-    let logfile = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{d} {l} {M} {m}\n")))
-        .build("koko.log")?;
 
-    let config = Config::builder()
-        .appender(Appender::builder().build("logfile", Box::new(logfile)))
-        .build(
-            Root::builder()
-                .appender("logfile")
-                .build(LevelFilter::Debug),
-        )
-        .map_err(|x| log4rs::config::InitError::BuildConfig(x))?;
-
-    log4rs::init_config(config).map_err(|x| log4rs::config::InitError::SetLogger(x))?;
-
-    Ok(())
-}
-
-// 1h4m3s -> 1*3600 + 4*60 + 3
-fn parse_time_as_seconds(str: &str) -> Result<u32, Error> {
-    enum State {
-        Begin,
-        Value(u32),
-        Mul,
+    let config = config.unwrap_or_default();
+    let (level, mut modules) = config.levels()?;
+    let mut level = level.unwrap_or(LevelFilter::Off);
+    if args.is_present("debug") {
+        level = LevelFilter::Debug;
     }
-    enum Class {
-        Digit(u8),
-        Multiplier(u32),
+    if let Some(spec) = args.value_of("log_level") {
+        let (spec_level, spec_modules) = logging::parse_levels(spec)?;
+        level = spec_level.unwrap_or(level);
+        // later ones win
+        modules.extend(spec_modules);
     }
-    let mut state = State::Begin;
-    let mut seconds = 0u32;
-    for char in str.chars() {
-        let class = match char {
-            char if char >= '0' && char <= '9' => Class::Digit(char as u8 - '0' as u8),
-            'h' => Class::Multiplier(3600),
-            'm' => Class::Multiplier(60),
-            's' => Class::Multiplier(1),
-            char => {
-                return Err(Error::ParseTimeError(format!(
-                    "Invalid character: {}",
-                    char
-                )))
-            }
-        };
-        match class {
-            Class::Digit(digit) => {
-                let value = match state {
-                    State::Value(value) => value,
-                    _ => 0,
-                };
-                state = State::Value(value * 10 + (digit as u32));
-            }
-            Class::Multiplier(mul) => {
-                let value = match state {
-                    State::Value(value) => value,
-                    _ => return Err(Error::ParseTimeError(String::from("Unexpected 'm'"))),
-                };
-                seconds += value * mul;
-                state = State::Mul;
-            }
-        }
+    if !logging::enabled(level, &modules) {
+        return Ok(());
     }
 
-    match state {
-        State::Mul => Ok(seconds),
-        _ => Err(Error::ParseTimeError(String::from(
-            "Expected time specifier at the end",
-        )))?,
-    }
+    let file = match args.value_of_os("log_file") {
+        Some(file) => PathBuf::from(file),
+        None => match config.file {
+            Some(file) => PathBuf::from(file),
+            None => logging::default_file().ok_or(logging::Error::NoLogFileError)?,
+        },
+    };
+    logging::init(&logging::Options {
+        level,
+        modules,
+        file,
+        format: args
+            .value_of("log_format")
+            .map(|x| x.parse::<config::LogFormat>().unwrap())
+            .or(config.format)
+            .unwrap_or_default(),
+        max_size_kb: config.max_size.unwrap_or(logging::DEFAULT_MAX_SIZE_KB),
+        keep: config.keep.unwrap_or(logging::DEFAULT_KEEP),
+    })?;
+
+    Ok(())
 }
 
 fn get_config_file(config_file_arg: Option<&str>) -> Result<String, Error> {
@@ -182,6 +188,335 @@ fn get_config_file(config_file_arg: Option<&str>) -> Result<String, Error> {
     Ok(config_file.to_string())
 }
 
+async fn notify(
+    kodi_address: std::net::IpAddr,
+    kodi_port: u16,
+    kodi_ws_port: u16,
+    kodi_auth: &Option<(String, String)>,
+    args: &clap::ArgMatches,
+    notifications: config::Notifications,
+) -> Result<(), Error> {
+    let url = url::Url::parse(format!("http://{}:{}/jsonrpc", kodi_address, kodi_port).as_str())?;
+    let wsurl =
+        url::Url::parse(format!("ws://{}:{}/jsonrpc", kodi_address, kodi_ws_port).as_str())?;
+    let mut jsonrpc_session = kodi_rpc::connect_any(&wsurl, &url, kodi_auth).await?;
+    let params = kodi_rpc_types::GUIShowNotificationParams {
+        title: args
+            .value_of("title")
+            .map(String::from)
+            .unwrap_or_else(|| notifications.title()),
+        message: String::from(args.value_of("MESSAGE").unwrap()),
+        image: args
+            .value_of("image")
+            .map(|x| x.parse::<kodi_rpc_types::GUINotificationImage>().unwrap()),
+        displaytime: args
+            .value_of("display_time")
+            .map(|x| x.parse::<u32>().unwrap())
+            .or(notifications.display_time)
+            .map(|seconds| seconds * 1000),
+    };
+    kodi_rpc::gui_show_notification(&mut jsonrpc_session, params).await?;
+    Ok(())
+}
+
+// Sends a single call and prints the result; with --follow keeps printing the notifications
+// until ctrl-c
+async fn rpc(
+    kodi_address: std::net::IpAddr,
+    kodi_port: u16,
+    kodi_ws_port: u16,
+    kodi_auth: &Option<(String, String)>,
+    args: &clap::ArgMatches,
+) -> Result<(), Error> {
+    let params = args
+        .value_of("PARAMS")
+        .map(serde_json::from_str::<serde_json::Value>)
+        .transpose()
+        .map_err(Error::ParamsError)?;
+    let url = url::Url::parse(format!("http://{}:{}/jsonrpc", kodi_address, kodi_port).as_str())?;
+    let wsurl =
+        url::Url::parse(format!("ws://{}:{}/jsonrpc", kodi_address, kodi_ws_port).as_str())?;
+    let mut jsonrpc_session = kodi_rpc::connect_any(&wsurl, &url, kodi_auth).await?;
+    let mut subscription = if args.is_present("follow") {
+        Some(kodi_rpc::subscribe(&mut jsonrpc_session).await?)
+    } else {
+        None
+    };
+    let result = kodi_rpc::call(
+        &mut jsonrpc_session,
+        args.value_of("METHOD").unwrap(),
+        params,
+    )
+    .await
+    .map_err(|err| Error::RpcError(kodi_rpc::describe_error(&err)))?;
+    println!("{}", serde_json::to_string_pretty(&result).unwrap());
+    if let Some(subscription) = subscription.as_mut() {
+        loop {
+            tokio::select! {
+                notification = subscription.next_raw() => match notification {
+                    Some(notification) => {
+                        println!("{}", serde_json::to_string_pretty(&notification.value).unwrap())
+                    }
+                    None => break,
+                },
+                _ = tokio::signal::ctrl_c() => break,
+            }
+        }
+    }
+    Ok(())
+}
+
+// whether a new file in the watched directory goes to a playlist of media_type; any media
+// will do for the first file, which decides the type
+fn accept_watched(path: &Path, media_type: Option<media::MediaType>) -> bool {
+    let file_type = match media::MediaType::recognize(path) {
+        Ok(Some(file_type)) => file_type,
+        Ok(None) => {
+            log::info!("Skipping {}: not a media file", path.to_string_lossy());
+            return false;
+        }
+        Err(err) => {
+            log::info!("Skipping {}: {}", path.to_string_lossy(), err);
+            return false;
+        }
+    };
+    match media_type {
+        Some(media_type)
+            if file_type != media_type
+                && (file_type, media_type)
+                    != (media::MediaType::Audio, media::MediaType::Video) =>
+        {
+            log::info!(
+                "Skipping {}: {:?} in a {:?} playlist",
+                path.to_string_lossy(),
+                file_type,
+                media_type
+            );
+            false
+        }
+        _ => true,
+    }
+}
+
+// Serves and enqueues the new files in the watched directory until the session is gone. Files
+// that don't fit the playlist of the session, like pictures in a video session, are skipped,
+// as are the initial ones already in the playlist, reported if they were still being written.
+async fn watch_files(
+    mut watcher: watch::Watcher,
+    mut initial: HashSet<PathBuf>,
+    served_files: server::ServedFiles,
+    mut enqueue_tx: futures::channel::mpsc::Sender<(url::Url, PathBuf)>,
+    media_type: media::MediaType,
+) {
+    use futures::SinkExt;
+    while let Some(path) = watcher.next().await {
+        let path = match path {
+            Ok(path) => path,
+            Err(err) => {
+                log::error!(
+                    "Failed to watch {}: {}",
+                    watcher.dir().to_string_lossy(),
+                    err
+                );
+                break;
+            }
+        };
+        if initial.remove(&path) {
+            log::debug!("{} is already in the playlist", path.to_string_lossy());
+            continue;
+        }
+        if !accept_watched(&path, Some(media_type)) {
+            continue;
+        }
+        let url = match served_files.add(path.clone()) {
+            Ok(url) => url,
+            Err(err) => {
+                log::error!("Failed to serve {}: {}", path.to_string_lossy(), err);
+                continue;
+            }
+        };
+        if enqueue_tx.send((url, path)).await.is_err() {
+            // the session has ended
+            break;
+        }
+    }
+}
+
+// Lists the sessions in the history that match the filters; with --replay returns the one to
+// play again instead
+fn history(args: &clap::ArgMatches) -> Result<Option<history::Session>, Error> {
+    let path = history::default_file().ok_or(Error::NoHistoryError)?;
+    let sessions = history::load(&path)?;
+    if let Some(number) = args.value_of("replay") {
+        let number = number.parse::<usize>()?;
+        return match number.checked_sub(1).and_then(|index| sessions.get(index)) {
+            Some(session) => Ok(Some(session.clone())),
+            None => Err(Error::NoSuchSessionError(number)),
+        };
+    }
+    let filter = history::Filter {
+        host: args.value_of("host").map(String::from),
+        file: args.value_of("file").map(String::from),
+        since: args
+            .value_of("since")
+            .map(|x| x.parse::<chrono::NaiveDate>().unwrap()),
+    };
+    let last = args.value_of("last").unwrap().parse::<usize>()?;
+    let matching: Vec<(usize, &history::Session)> = sessions
+        .iter()
+        .enumerate()
+        .filter(|(_, session)| filter.matches(session))
+        .collect();
+    if matching.is_empty() {
+        eprintln!("No sessions in {}", path.to_string_lossy());
+    }
+    // numbered from the start of the whole history, so that --replay can use the number
+    for (index, session) in matching.iter().skip(matching.len().saturating_sub(last)) {
+        let start = session
+            .start_time()
+            .map(|start| start.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| session.start.clone());
+        println!(
+            "{:4}  {}  {}  {}",
+            index + 1,
+            start,
+            session.host,
+            session.outcome
+        );
+        for item in &session.items {
+            let time = |ms| kodi_rpc_types::GlobalTime::from_milliseconds(ms).to_string();
+            let progress = match (item.finished, item.time_ms, item.total_ms) {
+                (true, _, _) => String::from("finished"),
+                (false, Some(ms), Some(total_ms)) => format!("{} / {}", time(ms), time(total_ms)),
+                (false, Some(ms), None) => time(ms),
+                (false, None, _) => String::from("not played"),
+            };
+            println!("      {}  {}", item.file.to_string_lossy(), progress);
+        }
+    }
+    Ok(None)
+}
+
+struct Source {
+    path: PathBuf,
+    start: Option<Position>,
+}
+
+struct Files {
+    files: HashMap<String, PathBuf>,
+    urls_order: HashMap<String, usize>,
+    urls_start: HashMap<String, Position>,
+}
+
+// file.mkv@12m30s -> file.mkv starting at 12m30s, unless file.mkv@12m30s exists
+fn split_source(source: &OsStr) -> Result<(PathBuf, Option<Position>), Error> {
+    let path = PathBuf::from(source);
+    if path.exists() {
+        return Ok((path, None));
+    }
+    let split = source.to_str().and_then(|source| {
+        source
+            .rfind('@')
+            .map(|at| (&source[..at], &source[at + 1..]))
+    });
+    match split {
+        Some((file, start)) if Path::new(file).exists() => {
+            Ok((PathBuf::from(file), Some(start.parse::<Position>()?)))
+        }
+        _ => Err(Error::FileNotFoundError(path)),
+    }
+}
+
+// directories are replaced with the media files in them, in name order; the rest, like
+// Thumbs.db or subtitles, are left out
+fn expand_sources<'a>(sources: impl Iterator<Item = &'a OsStr>) -> Result<Vec<Source>, Error> {
+    let mut result = Vec::new();
+    for source in sources {
+        let (path, start) = split_source(source)?;
+        if path.is_dir() {
+            let mut entries = Vec::new();
+            for entry in std::fs::read_dir(&path)? {
+                let entry = entry?.path();
+                let hidden = entry
+                    .file_name()
+                    .map(|name| name.to_string_lossy().starts_with('.'))
+                    .unwrap_or(false);
+                if !entry.is_file() || hidden {
+                    continue;
+                }
+                // the user didn't name the file, so one that can't be read isn't worth failing for
+                match media::MediaType::recognize(&entry) {
+                    Ok(Some(_)) => entries.push(entry),
+                    Ok(None) => (),
+                    Err(err) => log::warn!("Skipping {}: {}", entry.to_string_lossy(), err),
+                }
+            }
+            entries.sort();
+            result.extend(entries.into_iter().map(|path| Source { path, start }));
+        } else {
+            result.push(Source { path, start });
+        }
+    }
+    Ok(result)
+}
+
+fn collect_files(sources: Vec<Source>) -> Result<Files, Error> {
+    let mut files = HashMap::new();
+    let mut urls_order = HashMap::new();
+    let mut urls_start = HashMap::new();
+    let mut url_counts = HashMap::new();
+
+    let mut order_index = 0usize;
+
+    for Source { path, start } in sources {
+        if !path.exists() {
+            return Err(Error::FileNotFoundError(path));
+        }
+        let url_name = path
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .expect("TODO: filename is required to be valid UTF8")
+            .to_string();
+
+        let mut count = if url_counts.contains_key(&url_name) {
+            let count: &u32 = url_counts.get(&url_name).unwrap();
+            let count = count + 1;
+            url_counts.insert(url_name.clone(), count);
+            count
+        } else {
+            let count = 1;
+            url_counts.insert(url_name.clone(), count);
+            count
+        };
+
+        fn name(base: &str, count: u32) -> String {
+            if count == 1 {
+                base.to_string()
+            } else {
+                format!("{} #{}", base, count)
+            }
+        }
+
+        // maybe this algorithm gives wild names in some corner cases..
+        while files.contains_key(&name(&url_name, count)) {
+            count += 1;
+        }
+        files.insert(name(&url_name, count), path);
+        urls_order.insert(name(&url_name, count), order_index);
+        if let Some(start) = start {
+            urls_start.insert(name(&url_name, count), start);
+        }
+        order_index += 1;
+    }
+
+    Ok(Files {
+        files,
+        urls_order,
+        urls_start,
+    })
+}
+
 async fn actual_main() -> Result<(), Error> {
     let exit = exit::Exit::new();
 
@@ -189,12 +524,13 @@ async fn actual_main() -> Result<(), Error> {
         .version(get_version().as_str())
         .author("Erkki Seppälä <erkki.seppala@vincit.fi>")
         .about("Remote Kontroller and streamer for Kodi")
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
         .arg(
             clap::Arg::new("SOURCE")
                 .required(true)
                 .index(1)
                 .multiple(true)
-                .about("File to stream"),
+                .about("File, or directory of files, to stream"),
         )
         .arg(
             clap::Arg::new("config")
@@ -207,25 +543,39 @@ async fn actual_main() -> Result<(), Error> {
                         get_config_file(None)?
                     )
                     .as_str(),
-                ),
+                )
+                .global(true),
         )
         .arg(
             clap::Arg::new("kodi")
                 .long("kodi")
                 .short('k')
                 .takes_value(true)
-                .about("Address of the host running Kodi; defaults to localhost"),
+                .about("Address of the host running Kodi; defaults to localhost")
+                .global(true),
         )
         .arg(
             clap::Arg::new("kodi_port")
                 .long("port")
                 .default_value("8080")
                 .takes_value(true)
-                .about("Port to use for HTTP connection (9090 will always be used for WebSocket)")
+                .about("Port to use for HTTP connection")
                 .validator(|arg| match arg.parse::<u16>() {
                     Ok(_) => Ok(()),
                     Err(err) => Err(err.to_string()),
-                }),
+                })
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("kodi_ws_port")
+                .long("ws-port")
+                .takes_value(true)
+                .about("Port of Kodi's WebSocket; defaults to ws_port of the host config, or 9090")
+                .validator(|arg| match arg.parse::<u16>() {
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .global(true),
         )
         .arg(
             clap::Arg::new("server_port")
@@ -243,47 +593,326 @@ async fn actual_main() -> Result<(), Error> {
                 .short('u')
                 .default_value("kodi")
                 .takes_value(true)
-                .about("Username of the user for Kodi"),
+                .about("Username of the user for Kodi")
+                .global(true),
         )
         .arg(
             clap::Arg::new("password")
                 .long("pass")
                 .short('p')
                 .takes_value(true)
-                .about("Password for the user"),
+                .about("Password for the user")
+                .global(true),
         )
         .arg(
             clap::Arg::new("start")
                 .long("start")
                 .short('s')
                 .takes_value(true)
-                .about("Start position, like 5m, or 5m5s, or 5s")
-                .validator(|arg| match parse_time_as_seconds(arg) {
+                .about("Start position of the first file, like 5m5s, 1:02:03.5, 90 or 25%; other files can be given as file@5m")
+                .validator(|arg| match arg.parse::<Position>() {
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err.to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::new("end")
+                .long("end")
+                .takes_value(true)
+                .conflicts_with("duration")
+                .about("End position of the first file, in the same format as --start; the next item is played from there. With --shuffle this is still the first file given, whenever it plays")
+                .validator(|arg| match arg.parse::<Position>() {
                     Ok(_) => Ok(()),
                     Err(err) => Err(err.to_string()),
                 }),
         )
+        .arg(
+            clap::Arg::new("duration")
+                .long("duration")
+                .takes_value(true)
+                .about("How long to play from the start position, like 1m30s")
+                .validator(|arg| match arg.parse::<Position>() {
+                    Ok(Position::Milliseconds(_)) => Ok(()),
+                    Ok(Position::Percentage(_)) => Err(String::from("Duration must be a time")),
+                    Err(err) => Err(err.to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::new("repeat")
+                .long("repeat")
+                .takes_value(true)
+                .possible_values(&["one", "all"])
+                .about("Repeat the current item or the whole playlist"),
+        )
+        .arg(
+            clap::Arg::new("shuffle")
+                .long("shuffle")
+                .about("Play the files in random order"),
+        )
+        .arg(
+            clap::Arg::new("slide_duration")
+                .long("slide-duration")
+                .takes_value(true)
+                .about("Seconds to show each picture in a slideshow")
+                .validator(|arg| match arg.parse::<u32>() {
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err.to_string()),
+                }),
+        )
+        .arg(
+            clap::Arg::new("audio")
+                .long("audio")
+                .takes_value(true)
+                .about("Audio file to play in the background of a slideshow"),
+        )
+        .arg(
+            clap::Arg::new("append")
+                .long("append")
+                .about("Add the files after the items already in Kodi's playlist instead of replacing them"),
+        )
+        .arg(
+            clap::Arg::new("on_end")
+                .long("on-end")
+                .takes_value(true)
+                .possible_values(&["stop-and-home", "stop", "leave-playing", "restore"])
+                .about("What to do when the session ends; default is stop-and-home, or leave-playing in remote mode")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("wake")
+                .long("wake")
+                .about("Wake the host up with Wake-on-LAN first; needs mac in the host config")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("wake_timeout")
+                .long("wake-timeout")
+                .takes_value(true)
+                .default_value("60")
+                .about("Seconds to wait for the host to wake up")
+                .validator(|arg| match arg.parse::<u64>() {
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err.to_string()),
+                })
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("power")
+                .long("power")
+                .takes_value(true)
+                .possible_values(&["suspend", "shutdown"])
+                .about("Suspend or shut down the host when the session ends")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("record")
+                .long("record")
+                .takes_value(true)
+                .value_name("FILE")
+                .about("Record the JSON-RPC traffic of the session to FILE, for replaying in tests")
+                .global(true),
+        )
         .arg(
             clap::Arg::new("debug")
                 .long("debug")
                 .short('d')
-                .about("Write debug information"),
+                .about("Write debug information to the log; same as --log-level debug")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("log_level")
+                .long("log-level")
+                .takes_value(true)
+                .value_name("LEVEL")
+                .about("Log level (off, error, warn, info, debug, trace), optionally followed by per-module levels, like debug,kodi_kontrol::ui::poll=off")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("log_file")
+                .long("log-file")
+                .takes_value(true)
+                .value_name("FILE")
+                .about("Where to write the log; default koko.log in the XDG state directory")
+                .global(true),
+        )
+        .arg(
+            clap::Arg::new("log_format")
+                .long("log-format")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .about("Write the log as text or as JSON lines")
+                .global(true),
         )
         .arg(
             clap::Arg::new("public")
                 .long("public")
                 .about("Don't do IP-based access control"),
         )
+        .subcommand(
+            clap::App::new("remote")
+                .about("Control what Kodi is already playing, without streaming anything"),
+        )
+        .subcommand(
+            clap::App::new("doctor")
+                .about("Check step by step that Kodi can be controlled and can stream from here"),
+        )
+        .subcommand(
+            clap::App::new("rpc")
+                .about("Send a JSON-RPC call to Kodi and print the result")
+                .arg(
+                    clap::Arg::new("METHOD")
+                        .required(true)
+                        .index(1)
+                        .about("Method to call, like Player.GetActivePlayers"),
+                )
+                .arg(
+                    clap::Arg::new("PARAMS")
+                        .index(2)
+                        .about("Parameters as a JSON object or array, like '{\"playerid\": 1}'"),
+                )
+                .arg(
+                    clap::Arg::new("follow")
+                        .long("follow")
+                        .short('f')
+                        .about("Keep printing Kodi's notifications until ctrl-c"),
+                ),
+        )
+        .subcommand(
+            clap::App::new("history")
+                .about("List past streaming sessions, or play one of them again")
+                .arg(
+                    clap::Arg::new("host")
+                        .long("host")
+                        .takes_value(true)
+                        .about("Only sessions on this host"),
+                )
+                .arg(
+                    clap::Arg::new("file")
+                        .long("file")
+                        .takes_value(true)
+                        .about("Only sessions with a file whose path contains this"),
+                )
+                .arg(
+                    clap::Arg::new("since")
+                        .long("since")
+                        .takes_value(true)
+                        .value_name("YYYY-MM-DD")
+                        .about("Only sessions started on this day or later")
+                        .validator(|arg| match arg.parse::<chrono::NaiveDate>() {
+                            Ok(_) => Ok(()),
+                            Err(err) => Err(err.to_string()),
+                        }),
+                )
+                .arg(
+                    clap::Arg::new("last")
+                        .long("last")
+                        .short('n')
+                        .takes_value(true)
+                        .default_value("20")
+                        .about("How many of the latest matching sessions to list")
+                        .validator(|arg| match arg.parse::<usize>() {
+                            Ok(_) => Ok(()),
+                            Err(err) => Err(err.to_string()),
+                        }),
+                )
+                .arg(
+                    clap::Arg::new("replay")
+                        .long("replay")
+                        .takes_value(true)
+                        .value_name("NUMBER")
+                        .about("Play the session with this number again, continuing each file from where it was left")
+                        .validator(|arg| match arg.parse::<usize>() {
+                            Ok(_) => Ok(()),
+                            Err(err) => Err(err.to_string()),
+                        }),
+                ),
+        )
+        .subcommand(
+            clap::App::new("watch")
+                .about("Play the files in DIR and keep adding new ones as they appear, until ctrl-c")
+                .arg(
+                    clap::Arg::new("DIR")
+                        .required(true)
+                        .index(1)
+                        .about("Directory to watch"),
+                ),
+        )
+        .subcommand(
+            clap::App::new("notify")
+                .about("Show a notification on Kodi")
+                .arg(
+                    clap::Arg::new("MESSAGE")
+                        .required(true)
+                        .index(1)
+                        .about("Text of the notification"),
+                )
+                .arg(
+                    clap::Arg::new("title")
+                        .long("title")
+                        .takes_value(true)
+                        .about("Heading of the notification; default is koko"),
+                )
+                .arg(
+                    clap::Arg::new("image")
+                        .long("image")
+                        .takes_value(true)
+                        .possible_values(&["info", "warning", "error"])
+                        .about("Icon of the notification"),
+                )
+                .arg(
+                    clap::Arg::new("display_time")
+                        .long("display-time")
+                        .takes_value(true)
+                        .about("Seconds to show the notification")
+                        .validator(|arg| match arg.parse::<u32>() {
+                            Ok(_) => Ok(()),
+                            Err(err) => Err(err.to_string()),
+                        }),
+                ),
+        )
         .get_matches();
 
-    init_logging(args.is_present("debug"))?;
-
     let config_file = get_config_file(args.value_of("config"))?;
     let config = config::Config::load(&config_file)?;
-    let host = config.get_host(args.value_of("kodi"))?;
 
-    let kodi_address = resolve_address(host.hostname).await?;
-    let kodi_port = args.value_of("kodi_port").unwrap().parse::<u16>()?;
+    init_logging(&args, config.logging.clone())?;
+    log::info!("koko {} with config {}", get_version(), config_file);
+    let ui_options = ui::Options {
+        keymap: config.keymap().map_err(config::Error::from)?,
+        layout: config.layout.unwrap_or_default(),
+        theme: config.theme.clone().unwrap_or_default(),
+    };
+    let replay = match args.subcommand() {
+        Some(("history", history_args)) => match history(history_args)? {
+            Some(session) => Some(session),
+            None => return Ok(()),
+        },
+        _ => None,
+    };
+
+    // the session is replayed on the same host, unless told otherwise
+    let host_key = args
+        .value_of("kodi")
+        .or_else(|| replay.as_ref().map(|session| session.host.as_str()));
+    let host = config.get_host(host_key)?;
+    let history_host = host_key
+        .map(String::from)
+        .or_else(|| config.default.clone())
+        .or_else(|| host.hostname.clone())
+        .unwrap_or_default();
+
+    let mode = match args.subcommand() {
+        Some(("remote", _)) => kodi_control::Mode::Remote,
+        _ => kodi_control::Mode::Play,
+    };
+    // the commandline, then the host's config, then the global one
+    let on_end = args
+        .value_of("on_end")
+        .map(|x| x.parse::<config::OnEnd>().unwrap())
+        .or(host.on_end)
+        .or(config.on_end)
+        .unwrap_or_else(|| mode.default_on_end());
+
     let http_server_port = {
         let server_port = args
             .value_of("server_port")
@@ -312,65 +941,213 @@ async fn actual_main() -> Result<(), Error> {
         }
     };
 
-    let start_seconds = args
-        .value_of("start")
-        .map(|x| parse_time_as_seconds(x).unwrap());
+    let kodi_port = args.value_of("kodi_port").unwrap().parse::<u16>()?;
+    let kodi_ws_port = match args.value_of("kodi_ws_port") {
+        Some(port) => port.parse::<u16>()?,
+        None => host.ws_port.unwrap_or(9090),
+    };
 
-    let mut files = HashMap::new();
-    let mut urls_order = HashMap::new();
-    let mut url_counts = HashMap::new();
+    // before anything else that could fail on the connection
+    if let Some(("doctor", _)) = args.subcommand() {
+        let ok = doctor::run(doctor::Options {
+            hostname: host.hostname,
+            kodi_port,
+            kodi_ws_port,
+            auth: kodi_auth,
+            listen_port: http_server_port,
+        })
+        .await;
+        // the report is already printed; the exit status is for scripts
+        if !ok {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    let mut order_index = 0usize;
+    let kodi_address = resolve_address(host.hostname).await?;
 
-    for source in args.values_of_os("SOURCE").unwrap() {
-        let path: PathBuf = Path::new(source).to_path_buf();
-        if !path.exists() {
-            return Err(Error::FileNotFoundError(path));
+    if args.is_present("wake") {
+        let mac = host.mac.as_ref().ok_or(Error::NoMacError)?;
+        let timeout = args.value_of("wake_timeout").unwrap().parse::<u64>()?;
+        eprintln!("Waking up {}..", kodi_address);
+        wol::wake(
+            kodi_address,
+            mac,
+            &[kodi_port],
+            std::time::Duration::from_secs(timeout),
+        )
+        .await?;
+        // Kodi starts the WebSocket after the web server; it may also be firewalled, in which
+        // case the session falls back to HTTP anyway, so it isn't waited for long
+        if !wol::wait_for(
+            kodi_address,
+            &[kodi_ws_port],
+            std::time::Duration::from_secs(10),
+        )
+        .await
+        {
+            log::warn!("The WebSocket of {} isn't up yet", kodi_address);
         }
-        let url_name = path
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .expect("TODO: filename is required to be valid UTF8")
-            .to_string();
+    }
 
-        let mut count = if url_counts.contains_key(&url_name) {
-            let count: &u32 = url_counts.get(&url_name).unwrap();
-            let count = count + 1;
-            url_counts.insert(url_name.clone(), count);
-            count
-        } else {
-            let count = 1;
-            url_counts.insert(url_name.clone(), count);
-            count
-        };
+    if let Some(("notify", notify_args)) = args.subcommand() {
+        let notifications = config.notifications.unwrap_or_default();
+        return notify(
+            kodi_address,
+            kodi_port,
+            kodi_ws_port,
+            &kodi_auth,
+            notify_args,
+            notifications,
+        )
+        .await;
+    }
 
-        fn name(base: &str, count: u32) -> String {
-            if count == 1 {
-                base.to_string()
-            } else {
-                format!("{} #{}", base, count)
-            }
-        }
+    if let Some(("rpc", rpc_args)) = args.subcommand() {
+        return rpc(kodi_address, kodi_port, kodi_ws_port, &kodi_auth, rpc_args).await;
+    }
 
-        // maybe this algorithm gives wild names in some corner cases..
-        while files.contains_key(&name(&url_name, count)) {
-            count += 1;
+    let start = args
+        .value_of("start")
+        .map(|x| x.parse::<Position>().unwrap());
+
+    let end = match (args.value_of("end"), args.value_of("duration")) {
+        (Some(end), _) => Some(end.parse::<Position>().unwrap()),
+        (None, Some(duration)) => match (start, duration.parse::<Position>().unwrap()) {
+            (None, duration) => Some(duration),
+            (Some(Position::Milliseconds(start)), Position::Milliseconds(duration)) => {
+                match start.checked_add(duration) {
+                    Some(end) => Some(Position::Milliseconds(end)),
+                    None => {
+                        return Err(Error::ParseTimeError(String::from(
+                            "The start position and duration add up to too long",
+                        )))
+                    }
+                }
+            }
+            _ => {
+                return Err(Error::ParseTimeError(String::from(
+                    "Duration cannot be used with a percentage start position",
+                )))
+            }
+        },
+        (None, None) => None,
+    };
+    if let (Some(Position::Milliseconds(start)), Some(Position::Milliseconds(end))) = (start, end) {
+        if end <= start {
+            return Err(Error::ParseTimeError(String::from(
+                "End position must be after the start position",
+            )));
         }
-        files.insert(name(&url_name, count), path);
-        urls_order.insert(name(&url_name, count), order_index);
-        order_index += 1;
     }
 
-    let app_data = server::make_app_data_holder(server::AppData {
-        files,
-        urls_order,
-        kodi_address,
-        ip_access_control,
-        kodi_auth,
-        previously_logged_file: None,
-    });
+    let repeat = args
+        .value_of("repeat")
+        .map(|x| x.parse::<kodi_rpc_types::PlayerRepeat>().unwrap());
+    let slide_duration = args
+        .value_of("slide_duration")
+        .map(|x| x.parse::<u32>().unwrap())
+        .or(config.slide_duration);
+    let shuffle = args.is_present("shuffle");
+    let append = args.is_present("append");
+
+    let mut watcher = match args.subcommand() {
+        Some(("watch", watch_args)) => Some(watch::Watcher::new(Path::new(
+            watch_args.value_of_os("DIR").unwrap(),
+        ))?),
+        _ => None,
+    };
+
+    let (app_data, media_type, playlist) = match args.subcommand() {
+        Some(("remote", _)) => (None, media::MediaType::Video, Vec::new()),
+        _ => {
+            let mut sources = match (&replay, &mut watcher) {
+                (Some(session), _) => session
+                    .items
+                    .iter()
+                    .map(|item| Source {
+                        path: item.file.clone(),
+                        start: item.resume_position(),
+                    })
+                    .collect(),
+                (None, Some(watcher)) => {
+                    let mut sources = expand_sources(std::iter::once(watcher.dir().as_os_str()))?;
+                    if sources.is_empty() {
+                        eprintln!("Waiting for files in {}..", watcher.dir().to_string_lossy());
+                        while sources.is_empty() {
+                            match watcher.next().await {
+                                Some(path) => {
+                                    let path = path?;
+                                    if accept_watched(&path, None) {
+                                        sources.push(Source { path, start: None });
+                                    }
+                                }
+                                None => return Ok(()),
+                            }
+                        }
+                    }
+                    sources
+                }
+                (None, None) => expand_sources(args.values_of_os("SOURCE").unwrap())?,
+            };
+            if let Some(first) = sources.first_mut() {
+                first.start = first.start.or(start);
+            }
+            let playlist: Vec<PathBuf> = sources.iter().map(|source| source.path.clone()).collect();
+            let media_type =
+                media::MediaType::detect_all(sources.iter().map(|source| &source.path))?;
+            log::info!("Media type: {:?}", media_type);
+            // the audio is served like the rest of the files, it's just not in the playlist
+            let audio = args.value_of_os("audio").map(PathBuf::from);
+            if let Some(audio) = &audio {
+                if media_type != media::MediaType::Picture {
+                    return Err(Error::BackgroundAudioError);
+                }
+                sources.push(Source {
+                    path: audio.clone(),
+                    start: None,
+                });
+            }
+            let Files {
+                files,
+                mut urls_order,
+                urls_start,
+            } = collect_files(sources)?;
+            let background_audio = match audio {
+                None => None,
+                Some(_) => {
+                    let last = urls_order.len() - 1;
+                    let name = urls_order
+                        .iter()
+                        .find(|(_name, index)| **index == last)
+                        .map(|(name, _index)| name.clone())
+                        .unwrap();
+                    urls_order.remove(&name);
+                    Some(name)
+                }
+            };
+            let app_data = server::make_app_data_holder(server::AppData {
+                files,
+                urls_order,
+                urls_start,
+                background_audio,
+                kodi_address,
+                ip_access_control,
+                kodi_auth: kodi_auth.clone(),
+                previously_logged_file: None,
+            });
+            (Some(app_data), media_type, playlist)
+        }
+    };
     let (session_tx, session_rx) = tokio::sync::oneshot::channel::<server::Session>();
+    let (enqueue_tx, enqueue_rx) = match watcher {
+        Some(_) => {
+            let (enqueue_tx, enqueue_rx) = futures::channel::mpsc::channel(16);
+            (Some(enqueue_tx), Some(enqueue_rx))
+        }
+        None => (None, None),
+    };
+    let initial_files: HashSet<PathBuf> = playlist.iter().cloned().collect();
     let app_join: tokio::task::JoinHandle<Result<(), kodi_kontrol::error::Error>> = {
         let exit = exit.clone();
         tokio::task::spawn(async move {
@@ -382,6 +1159,17 @@ async fn actual_main() -> Result<(), Error> {
                 }
                 Ok(session) => {
                     log::debug!("Got session");
+                    if let (Some(watcher), Some(enqueue_tx), Some(served_files)) =
+                        (watcher, enqueue_tx, session.served_files())
+                    {
+                        tokio::spawn(watch_files(
+                            watcher,
+                            initial_files,
+                            served_files,
+                            enqueue_tx,
+                            media_type,
+                        ));
+                    }
                     match session.finish().await {
                         Ok(()) => {
                             exit.signal();
@@ -404,7 +1192,8 @@ async fn actual_main() -> Result<(), Error> {
     let ui_join = tokio::task::spawn_blocking({
         let exit = exit.clone();
         move || {
-            let mut ui = util::sync_panic_error(|| Ok(ui::Ui::new(kodi_control, exit)?));
+            let mut ui =
+                util::sync_panic_error(|| Ok(ui::Ui::new(kodi_control, exit, ui_options)?));
 
             ui_control_tx
                 .send(ui.control())
@@ -421,19 +1210,55 @@ async fn actual_main() -> Result<(), Error> {
         .expect("Failed to receive from ui_control_rx");
 
     let kodi_control_args = kodi_control::Args {
-        kodi_control_rx,
-        start_seconds,
+        on_end,
+        media_type,
+        end,
+        repeat,
+        shuffle,
+        append,
+        slide_duration,
+        notifications: config.notifications.clone().unwrap_or_default(),
+        power: args
+            .value_of("power")
+            .map(|x| x.parse::<config::Power>().unwrap())
+            .or(host.power),
+        record: args.value_of_os("record").map(PathBuf::from),
+        history: match (&app_data, config.history, history::default_file()) {
+            (Some(_), Some(true) | None, Some(path)) => {
+                Some(history::Recorder::new(path, history_host, playlist))
+            }
+            _ => None,
+        },
+        enqueue_rx,
+        ..kodi_control::Args::new(kodi_control_rx, mode)
     };
 
-    let session_result = server::Session::new(
-        app_data,
-        kodi_port,
-        http_server_port,
-        session_tx,
-        exit.clone(),
-        kodi_control_args,
-    )
-    .await;
+    let session_result = match app_data {
+        Some(app_data) => {
+            server::Session::new(
+                app_data,
+                kodi_port,
+                kodi_ws_port,
+                http_server_port,
+                session_tx,
+                exit.clone(),
+                kodi_control_args,
+            )
+            .await
+        }
+        None => {
+            server::Session::new_remote(
+                kodi_address,
+                kodi_port,
+                kodi_ws_port,
+                kodi_auth,
+                session_tx,
+                exit.clone(),
+                kodi_control_args,
+            )
+            .await
+        }
+    };
     ui_control.quit();
     ui_join.await.expect("Failed to join ui_join");
     match app_join.await.expect("Failed to join app_join") {
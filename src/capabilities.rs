@@ -0,0 +1,208 @@
+use crate::kodi_rpc_types::JSONRPCVersion;
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+// What the connected Kodi supports, from JSONRPC.Version and JSONRPC.Introspect. Until
+// (or unless) that's known, everything is assumed to be supported.
+#[derive(Debug, Clone, Default)]
+pub struct Capabilities {
+    pub version: Option<JSONRPCVersion>,
+    introspected: bool,
+    methods: HashSet<String>,
+    notifications: HashSet<String>,
+    // Player.Property.Name
+    player_properties: HashSet<String>,
+    // Player.Seek takes {"percentage": 50} etc. instead of bare values like 50
+    legacy_seek: bool,
+}
+
+// what doesn't work without each method or notification
+const LIMITATIONS: &[(&str, &str)] = &[
+    (
+        "Player.OnAVStart",
+        "Playback start is detected from Player.OnPlay, so start positions may be applied too early",
+    ),
+    (
+        "Player.GetItem",
+        "Start positions of other than the first file and --end/--duration are not available",
+    ),
+    (
+        "Playlist.GetItems",
+        "Kodi's existing playlist cannot be saved and restored",
+    ),
+    (
+        "Settings.SetSettingValue",
+        "--slide-duration is not available",
+    ),
+    (
+        "XBMC.GetInfoLabels",
+        "Chapter marks and the chapter list are not available",
+    ),
+    (
+        "Input.ExecuteAction",
+        "Chapter navigation is not available",
+    ),
+    (
+        "Input.SendText",
+        "Sending text in passthrough mode is not available",
+    ),
+    (
+        "GUI.ShowNotification",
+        "Notifications are not available",
+    ),
+];
+
+// whether the schema has an object with the given property anywhere inside
+fn has_property(schema: &Value, property: &str) -> bool {
+    match schema {
+        Value::Object(map) => {
+            let direct = map
+                .get("properties")
+                .and_then(|properties| properties.as_object())
+                .map(|properties| properties.contains_key(property))
+                .unwrap_or(false);
+            direct || map.values().any(|value| has_property(value, property))
+        }
+        Value::Array(array) => array.iter().any(|value| has_property(value, property)),
+        _ => false,
+    }
+}
+
+fn keys(schema: &Value, field: &str) -> HashSet<String> {
+    schema
+        .get(field)
+        .and_then(|value| value.as_object())
+        .map(|map| map.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+impl Capabilities {
+    pub fn new(version: Option<JSONRPCVersion>, introspect: &Value) -> Capabilities {
+        let player_properties = introspect
+            .pointer("/types/Player.Property.Name/enum")
+            .and_then(|value| value.as_array())
+            .map(|names| {
+                names
+                    .iter()
+                    .filter_map(|name| name.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        // the object format came with API v10 (Kodi 18); the schema tells for sure
+        let seek_value = introspect
+            .pointer("/methods/Player.Seek/params")
+            .and_then(|params| params.as_array())
+            .and_then(|params| {
+                params
+                    .iter()
+                    .find(|param| param.get("name") == Some(&Value::from("value")))
+            });
+        let legacy_seek = match (seek_value, version) {
+            (Some(value), _) => !has_property(value, "percentage"),
+            (None, Some(version)) => version.major < 10,
+            (None, None) => false,
+        };
+        Capabilities {
+            version,
+            introspected: true,
+            methods: keys(introspect, "methods"),
+            notifications: keys(introspect, "notifications"),
+            player_properties,
+            legacy_seek,
+        }
+    }
+
+    pub fn has_method(&self, method: &str) -> bool {
+        !self.introspected || self.methods.contains(method)
+    }
+
+    pub fn has_notification(&self, notification: &str) -> bool {
+        !self.introspected || self.notifications.contains(notification)
+    }
+
+    pub fn has_player_property(&self, property: &str) -> bool {
+        !self.introspected || self.player_properties.contains(property)
+    }
+
+    pub fn legacy_seek(&self) -> bool {
+        self.legacy_seek
+    }
+
+    // plain-language descriptions of what doesn't work with this Kodi
+    pub fn limitations(&self) -> Vec<String> {
+        let version = match self.version {
+            Some(version) => format!("JSON-RPC API v{}", version),
+            None => String::from("an unknown JSON-RPC API version"),
+        };
+        let mut limitations: Vec<String> = LIMITATIONS
+            .iter()
+            .filter(|(name, _)| !self.has_method(name) && !self.has_notification(name))
+            .map(|(name, message)| format!("{}: Kodi ({}) lacks {}", message, version, name))
+            .collect();
+        for property in &["currentvideostream", "currentaudiostream"] {
+            if !self.has_player_property(property) {
+                limitations.push(format!(
+                    "Stopping and the end of playback may be mixed up: Kodi ({}) lacks {}",
+                    version, property
+                ));
+            }
+        }
+        limitations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capabilities() {
+        let introspect = serde_json::json!({
+            "methods": {
+                "Player.Seek": {
+                    "params": [
+                        { "name": "playerid" },
+                        { "name": "value", "type": [
+                            { "type": "number" },
+                            { "type": "string", "enum": ["smallforward"] },
+                            { "type": "object", "properties": { "seconds": {} } }
+                        ]}
+                    ]
+                },
+                "Player.GetItem": {},
+                "Input.ExecuteAction": {}
+            },
+            "notifications": { "Player.OnPlay": {} },
+            "types": {
+                "Player.Property.Name": { "enum": ["time", "currentvideostream", "currentaudiostream"] }
+            }
+        });
+        let version = JSONRPCVersion {
+            major: 8,
+            minor: 0,
+            patch: 0,
+        };
+        let capabilities = Capabilities::new(Some(version), &introspect);
+        assert!(capabilities.legacy_seek());
+        assert!(capabilities.has_method("Player.GetItem"));
+        assert!(!capabilities.has_method("Input.SendText"));
+        assert!(!capabilities.has_notification("Player.OnAVStart"));
+        assert!(capabilities.has_player_property("currentvideostream"));
+        let limitations = capabilities.limitations();
+        assert!(limitations.iter().any(|x| x.contains("Player.OnAVStart")));
+        assert!(!limitations.iter().any(|x| x.contains("Player.GetItem")));
+
+        let mut introspect = introspect;
+        introspect["methods"]["Player.Seek"]["params"][1]["type"][0] =
+            serde_json::json!({ "type": "object", "properties": { "percentage": {} } });
+        assert!(!Capabilities::new(Some(version), &introspect).legacy_seek());
+
+        // nothing known: assume everything works
+        let unknown = Capabilities::default();
+        assert!(unknown.has_method("Input.SendText"));
+        assert!(!unknown.legacy_seek());
+        assert!(unknown.limitations().is_empty());
+    }
+}
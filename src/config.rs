@@ -6,16 +6,260 @@ use std::io;
 use std::io::Write;
 use thiserror::Error;
 
+use crate::{keymap, logging};
+
+use cursive::theme::{Color, PaletteColor};
+use log::LevelFilter;
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Credentials {}
 
+// What to do to Kodi when the session ends
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum OnEnd {
+    #[serde(rename = "stop-and-home")]
+    StopAndHome,
+    #[serde(rename = "stop")]
+    Stop,
+    #[serde(rename = "leave-playing")]
+    LeavePlaying,
+    #[serde(rename = "restore")]
+    Restore,
+}
+
+impl std::str::FromStr for OnEnd {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<OnEnd, String> {
+        match s {
+            "stop-and-home" => Ok(OnEnd::StopAndHome),
+            "stop" => Ok(OnEnd::Stop),
+            "leave-playing" => Ok(OnEnd::LeavePlaying),
+            "restore" => Ok(OnEnd::Restore),
+            other => Err(format!("Invalid session end policy: {}", other)),
+        }
+    }
+}
+
+// The [notifications] section: templates for notifications shown on Kodi during a
+// streaming session. Templates that are left out aren't shown.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Notifications {
+    // heading of all notifications, default "koko"
+    pub title: Option<String>,
+    // seconds to show each notification; Kodi's default is 5
+    pub display_time: Option<u32>,
+    // when the session starts; {count} is the number of files
+    pub start: Option<String>,
+    // when an item starts playing; {title} and {position} (1-based) of the item, {count}
+    pub item: Option<String>,
+    // when the session ends; {count} as in start
+    pub end: Option<String>,
+    // when the session fails instead; {error} is what went wrong
+    pub error: Option<String>,
+}
+
+impl Notifications {
+    pub fn title(&self) -> String {
+        self.title.clone().unwrap_or_else(|| String::from("koko"))
+    }
+}
+
+// replaces {name} with the value of name
+pub fn fill_template(template: &str, values: &[(&str, String)]) -> String {
+    values
+        .iter()
+        .fold(String::from(template), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), value)
+        })
+}
+
+// How the UI is laid out; auto picks compact for small terminals
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Layout {
+    #[serde(rename = "auto")]
+    Auto,
+    #[serde(rename = "full")]
+    Full,
+    #[serde(rename = "compact")]
+    Compact,
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout::Auto
+    }
+}
+
+impl std::str::FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Layout, String> {
+        match s {
+            "auto" => Ok(Layout::Auto),
+            "full" => Ok(Layout::Full),
+            "compact" => Ok(Layout::Compact),
+            other => Err(format!("Invalid layout: {}", other)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum ThemeBase {
+    #[serde(rename = "dark")]
+    Dark,
+    #[serde(rename = "light")]
+    Light,
+}
+
+impl Default for ThemeBase {
+    fn default() -> ThemeBase {
+        ThemeBase::Dark
+    }
+}
+
+// The [theme] section: a built-in base theme or a Cursive TOML theme file, with
+// optional palette overrides on top
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Theme {
+    pub base: Option<ThemeBase>,
+    pub file: Option<String>,
+    // palette role (e.g. "primary") -> color (e.g. "light white" or "#ffcc00")
+    pub colors: Option<BTreeMap<String, String>>,
+}
+
+const PALETTE_COLORS: &[(&str, PaletteColor)] = &[
+    ("background", PaletteColor::Background),
+    ("shadow", PaletteColor::Shadow),
+    ("view", PaletteColor::View),
+    ("primary", PaletteColor::Primary),
+    ("secondary", PaletteColor::Secondary),
+    ("tertiary", PaletteColor::Tertiary),
+    ("title_primary", PaletteColor::TitlePrimary),
+    ("title_secondary", PaletteColor::TitleSecondary),
+    ("highlight", PaletteColor::Highlight),
+    ("highlight_inactive", PaletteColor::HighlightInactive),
+    ("highlight_text", PaletteColor::HighlightText),
+];
+
+impl Theme {
+    pub fn load_file(&self) -> Result<Option<cursive::theme::Theme>, Error> {
+        self.file
+            .as_ref()
+            .map(|file| {
+                cursive::theme::load_theme_file(file)
+                    .map_err(|err| Error::ThemeError(format!("{}: {:?}", file, err)))
+            })
+            .transpose()
+    }
+
+    // the colors to set on top of the base theme
+    pub fn palette_colors(&self) -> Result<Vec<(PaletteColor, Color)>, Error> {
+        self.colors
+            .iter()
+            .flatten()
+            .map(|(role, color)| {
+                let role = PALETTE_COLORS
+                    .iter()
+                    .find(|(name, _)| *name == role.as_str())
+                    .map(|(_, role)| *role)
+                    .ok_or_else(|| Error::ThemeError(format!("Unknown palette color {}", role)))?;
+                let color = Color::parse(color)
+                    .ok_or_else(|| Error::ThemeError(format!("Invalid color {}", color)))?;
+                Ok((role, color))
+            })
+            .collect()
+    }
+}
+
+// What to do to the Kodi host after the session has ended
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum Power {
+    #[serde(rename = "suspend")]
+    Suspend,
+    #[serde(rename = "shutdown")]
+    Shutdown,
+}
+
+impl std::str::FromStr for Power {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Power, String> {
+        match s {
+            "suspend" => Ok(Power::Suspend),
+            "shutdown" => Ok(Power::Shutdown),
+            other => Err(format!("Invalid power action: {}", other)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    #[serde(rename = "text")]
+    Text,
+    // one JSON object per line
+    #[serde(rename = "json")]
+    Json,
+}
+
+impl Default for LogFormat {
+    fn default() -> LogFormat {
+        LogFormat::Text
+    }
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<LogFormat, String> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("Invalid log format: {}", other)),
+        }
+    }
+}
+
+// The [logging] section; the command line options override these
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Logging {
+    // off, error, warn, info, debug or trace; default off
+    pub level: Option<String>,
+    // default koko.log in the XDG state directory
+    pub file: Option<String>,
+    pub format: Option<LogFormat>,
+    // kilobytes before the file is rotated, default 10240
+    pub max_size: Option<u64>,
+    // rotated files to keep, default 3
+    pub keep: Option<u32>,
+    // module -> level, e.g. "kodi_kontrol::ui::poll" = "off"
+    pub modules: Option<BTreeMap<String, String>>,
+}
+
+impl Logging {
+    // the overall level and the module levels
+    pub fn levels(&self) -> Result<(Option<LevelFilter>, logging::ModuleLevels), logging::Error> {
+        let (level, mut modules) = logging::parse_levels(self.level.as_deref().unwrap_or(""))?;
+        for (module, level) in self.modules.iter().flatten() {
+            modules.push((module.clone(), logging::parse_level(level)?));
+        }
+        Ok((level, modules))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
 pub struct Host {
     pub hostname: Option<String>,
     pub port: Option<u16>,
+    // Kodi's WebSocket JSON-RPC, by default 9090
+    pub ws_port: Option<u16>,
     pub username: Option<String>,
     pub password: Option<String>,
     pub listen_port: Option<u16>,
+    pub on_end: Option<OnEnd>,
+    // for Wake-on-LAN, like aa:bb:cc:dd:ee:ff
+    pub mac: Option<String>,
+    pub power: Option<Power>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
@@ -23,6 +267,16 @@ pub struct Config {
     pub default: Option<String>,
     pub host: BTreeMap<String, Host>,
     pub listen_port: Option<u16>,
+    pub on_end: Option<OnEnd>,
+    pub slide_duration: Option<u32>,
+    // key name -> action, see keymap
+    pub keys: Option<BTreeMap<String, String>>,
+    pub layout: Option<Layout>,
+    pub theme: Option<Theme>,
+    pub notifications: Option<Notifications>,
+    pub logging: Option<Logging>,
+    // whether to keep a history of the sessions, default true
+    pub history: Option<bool>,
 }
 
 impl Config {
@@ -104,6 +358,15 @@ pub enum Error {
 
     #[error("Cannot determine default host: {}", .0)]
     DefaultHostError(String),
+
+    #[error(transparent)]
+    KeymapError(#[from] keymap::Error),
+
+    #[error("Invalid theme: {}", .0)]
+    ThemeError(String),
+
+    #[error(transparent)]
+    LoggingError(#[from] logging::Error),
 }
 
 pub static FILENAME: &str = "koko.ini";
@@ -134,10 +397,27 @@ impl Config {
             }
             Err(error) => return Err(Error::TomlDeError(error)),
         };
-        log::info!("Loaded config from {}", filename);
+        let config: Config = config;
+        // catch bad key bindings, colors and log levels before starting anything; logging
+        // isn't set up yet
+        config.keymap()?;
+        if let Some(theme) = &config.theme {
+            theme.load_file()?;
+            theme.palette_colors()?;
+        }
+        if let Some(logging) = &config.logging {
+            logging.levels()?;
+        }
         Ok(config)
     }
 
+    pub fn keymap(&self) -> Result<keymap::Keymap, keymap::Error> {
+        match &self.keys {
+            None => Ok(keymap::Keymap::default()),
+            Some(keys) => keymap::Keymap::new(keys),
+        }
+    }
+
     pub fn save(self, filename: &str) -> Result<(), Error> {
         let contents = toml::to_string(&self)?;
         let writer = atomicwrites::AtomicFile::new(filename, atomicwrites::AllowOverwrite);
@@ -162,8 +442,124 @@ mod tests {
                 port: Some(42),
                 username: Some(String::from("helo")),
                 password: Some(String::from("world")),
+                ..Default::default()
             },
         );
         config.save("test.ini").unwrap();
     }
+
+    #[test]
+    fn test_on_end() {
+        let config: Config = toml::from_str(
+            r#"
+on_end = "leave-playing"
+
+[host.tv]
+on_end = "restore"
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.on_end, Some(OnEnd::LeavePlaying));
+        assert_eq!(
+            config.get_host(Some("tv")).unwrap().on_end,
+            Some(OnEnd::Restore)
+        );
+    }
+
+    #[test]
+    fn test_fill_template() {
+        assert_eq!(
+            fill_template(
+                "Playing {title} ({position}/{count}) {other}",
+                &[
+                    ("title", String::from("foo")),
+                    ("position", String::from("1")),
+                    ("count", String::from("2")),
+                ]
+            ),
+            "Playing foo (1/2) {other}"
+        );
+    }
+
+    #[test]
+    fn test_theme() {
+        let config: Config = toml::from_str(
+            r#"
+layout = "compact"
+
+[theme]
+base = "light"
+
+[theme.colors]
+primary = "black"
+
+[host.tv]
+"#,
+        )
+        .unwrap();
+        assert_eq!(config.layout, Some(Layout::Compact));
+        let theme = config.theme.unwrap();
+        assert_eq!(theme.base, Some(ThemeBase::Light));
+        assert_eq!(
+            theme.palette_colors().unwrap(),
+            vec![(PaletteColor::Primary, Color::parse("black").unwrap())]
+        );
+        assert_eq!(
+            theme.colors.unwrap().get("primary").map(String::as_str),
+            Some("black")
+        );
+
+        let colors = |role: &str, color: &str| Theme {
+            colors: Some(
+                vec![(String::from(role), String::from(color))]
+                    .into_iter()
+                    .collect(),
+            ),
+            ..Theme::default()
+        };
+        assert!(colors("primary", "blurple").palette_colors().is_err());
+        assert!(colors("tetriary", "red").palette_colors().is_err());
+    }
+
+    #[test]
+    fn test_logging() {
+        let config: Config = toml::from_str(
+            r#"
+[logging]
+level = "debug"
+format = "json"
+max_size = 512
+
+[logging.modules]
+"kodi_kontrol::ui::poll" = "off"
+
+[host.tv]
+"#,
+        )
+        .unwrap();
+        let logging = config.logging.unwrap();
+        assert_eq!(
+            logging.levels().unwrap(),
+            (
+                Some(LevelFilter::Debug),
+                vec![(String::from("kodi_kontrol::ui::poll"), LevelFilter::Off)]
+            )
+        );
+        let level = |level: &str| Logging {
+            level: Some(String::from(level)),
+            ..Logging::default()
+        };
+        assert!(level("verbose").levels().is_err());
+        assert_eq!(logging.level.as_deref(), Some("debug"));
+        assert_eq!(logging.format, Some(LogFormat::Json));
+        assert_eq!(logging.max_size, Some(512));
+        assert_eq!(
+            logging
+                .modules
+                .unwrap()
+                .get("kodi_kontrol::ui::poll")
+                .map(String::as_str),
+            Some("off")
+        );
+    }
 }
@@ -0,0 +1,403 @@
+use crate::{capabilities::Capabilities, error, kodi_api, kodi_rpc, server};
+
+use actix_web::{App, HttpServer};
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use trust_dns_resolver::AsyncResolver;
+
+use url::Url;
+
+// Where to find Kodi, as for a normal session
+pub struct Options {
+    pub hostname: Option<String>,
+    pub kodi_port: u16,
+    pub kodi_ws_port: u16,
+    pub auth: Option<(String, String)>,
+    pub listen_port: u16,
+}
+
+enum Outcome {
+    Ok(String),
+    // what went wrong, and how to fix it
+    Fail(String, String),
+    // an earlier step failed, so this can't be checked
+    Skip,
+}
+
+// a sleeping or firewalled host may not respond at all
+const STEP_TIMEOUT: Duration = Duration::from_secs(10);
+
+const PROBE_NAME: &str = "koko-doctor-probe";
+
+const CONTROL_SETTINGS: &str = "Settings > Services > Control";
+
+// setting id, and its label in Kodi
+const SETTINGS: &[(&str, &str)] = &[
+    ("services.webserver", "Allow remote control via HTTP"),
+    (
+        "services.esenabled",
+        "Allow remote control from applications on this system",
+    ),
+    (
+        "services.esallinterfaces",
+        "Allow remote control from applications on other systems",
+    ),
+];
+
+fn report(step: &str, outcome: &Outcome) -> bool {
+    match outcome {
+        Outcome::Ok(message) => {
+            println!("[ ok ] {}: {}", step, message);
+            true
+        }
+        Outcome::Fail(message, fix) => {
+            println!("[FAIL] {}: {}", step, message);
+            println!("       Fix: {}", fix);
+            false
+        }
+        Outcome::Skip => {
+            println!("[skip] {}", step);
+            false
+        }
+    }
+}
+
+async fn with_timeout<T, F>(future: F) -> Result<T, error::Error>
+where
+    F: Future<Output = Result<T, error::Error>>,
+{
+    match tokio::time::timeout(STEP_TIMEOUT, future).await {
+        Ok(result) => result,
+        Err(_) => Err(error::Error::MsgError(format!(
+            "no response in {} seconds",
+            STEP_TIMEOUT.as_secs()
+        ))),
+    }
+}
+
+async fn resolve(hostname: &Option<String>) -> (Outcome, Option<IpAddr>) {
+    let hostname = match hostname {
+        // like a normal session
+        None => {
+            return (
+                Outcome::Ok(String::from("using 127.0.0.1")),
+                Some([127, 0, 0, 1].into()),
+            )
+        }
+        Some(hostname) => hostname,
+    };
+    if let Ok(address) = hostname.parse::<IpAddr>() {
+        return (
+            Outcome::Ok(format!("{} is an address", address)),
+            Some(address),
+        );
+    }
+    let lookup = match AsyncResolver::tokio_from_system_conf() {
+        Ok(resolver) => resolver.lookup_ip(hostname.as_str()).await,
+        Err(err) => Err(err),
+    };
+    match lookup.map(|ips| ips.iter().next()) {
+        Ok(Some(address)) => (Outcome::Ok(format!("{} is {}", hostname, address)), Some(address)),
+        Ok(None) => (
+            Outcome::Fail(
+                format!("{} has no addresses", hostname),
+                String::from("Use the IP address of the Kodi host instead, e.g. -k 192.168.1.2"),
+            ),
+            None,
+        ),
+        Err(err) => (
+            Outcome::Fail(
+                format!("cannot resolve {}: {}", hostname, err),
+                String::from(
+                    "Check the host name, or use the IP address of the Kodi host instead, e.g. -k 192.168.1.2",
+                ),
+            ),
+            None,
+        ),
+    }
+}
+
+async fn check_http(url: &Url, auth: &Option<(String, String)>) -> (Outcome, Option<IpAddr>) {
+    match with_timeout(kodi_rpc::jsonrpc_get(url, auth)).await {
+        Ok(result) => (
+            Outcome::Ok(format!("{} answers", url)),
+            Some(result.local_addr.ip()),
+        ),
+        Err(err @ error::Error::HttpErrorCode(hyper::http::StatusCode::UNAUTHORIZED)) => (
+            Outcome::Fail(
+                err.to_string(),
+                format!(
+                    "Kodi rejected the user name or password. Set them with -u/-p or user and password in the host config; Kodi has them under {}.",
+                    CONTROL_SETTINGS
+                ),
+            ),
+            None,
+        ),
+        Err(err @ error::Error::IOError(_)) => (
+            Outcome::Fail(
+                err.to_string(),
+                format!(
+                    "Nothing answers on port {}. In Kodi, enable \"{}\" under {} and check that --port matches its port.",
+                    url.port().unwrap_or(80),
+                    SETTINGS[0].1,
+                    CONTROL_SETTINGS
+                ),
+            ),
+            None,
+        ),
+        Err(err) => (
+            Outcome::Fail(
+                err.to_string(),
+                String::from("Check that the host runs Kodi and that --port points to its web server."),
+            ),
+            None,
+        ),
+    }
+}
+
+async fn check_ws(wsurl: &Url) -> (Outcome, Option<kodi_rpc::WsJsonRPCSession>) {
+    let port = wsurl.port().unwrap_or(9090);
+    match with_timeout(kodi_rpc::connect(wsurl)).await {
+        Ok(session) => (
+            Outcome::Ok(format!("connected to {}", wsurl)),
+            Some(session),
+        ),
+        Err(err) => (
+            Outcome::Fail(
+                err.to_string(),
+                format!(
+                    "In Kodi, enable \"{}\" under {}, and allow port {} in Kodi's firewall. Without the WebSocket koko polls Kodi over HTTP, which works but reacts slower.",
+                    SETTINGS[2].1, CONTROL_SETTINGS, port
+                ),
+            ),
+            None,
+        ),
+    }
+}
+
+fn check_capabilities(capabilities: &Capabilities) -> Outcome {
+    let version = match capabilities.version {
+        Some(version) => format!("v{}", version),
+        None => String::from("unknown version"),
+    };
+    let limitations = capabilities.limitations();
+    if limitations.is_empty() {
+        Outcome::Ok(version)
+    } else {
+        Outcome::Ok(format!(
+            "{}, with limitations:\n       {}",
+            version,
+            limitations.join("\n       ")
+        ))
+    }
+}
+
+// over the WebSocket if it works, otherwise over HTTP
+async fn get_setting(
+    session: &mut Option<kodi_rpc::WsJsonRPCSession>,
+    http_url: &Url,
+    setting: &str,
+) -> Result<serde_json::Value, error::Error> {
+    match session {
+        Some(session) => {
+            let params = kodi_api::SettingsGetSettingValueParams {
+                setting: String::from(setting),
+            };
+            let returns =
+                with_timeout(kodi_api::settings_get_setting_value(session, params)).await?;
+            Ok(serde_json::to_value(returns.value).expect("Failed to serialize a setting"))
+        }
+        None => {
+            let value = with_timeout(kodi_rpc::http_jsonrpc_get_setting(http_url, setting)).await?;
+            Ok(value.get("value").cloned().unwrap_or(value))
+        }
+    }
+}
+
+async fn check_setting(
+    session: &mut Option<kodi_rpc::WsJsonRPCSession>,
+    http_url: &Url,
+    setting: &str,
+    label: &str,
+    local: bool,
+) -> Outcome {
+    match get_setting(session, http_url, setting).await {
+        Ok(serde_json::Value::Bool(true)) => Outcome::Ok(String::from("on")),
+        Ok(serde_json::Value::Bool(false)) if local && setting == "services.esallinterfaces" => {
+            Outcome::Ok(String::from("off, but Kodi is on this machine"))
+        }
+        Ok(serde_json::Value::Bool(false)) => Outcome::Fail(
+            String::from("off"),
+            format!("In Kodi, enable \"{}\" under {}.", label, CONTROL_SETTINGS),
+        ),
+        Ok(value) => Outcome::Fail(
+            format!("unexpected value {}", value),
+            String::from("This Kodi may be too old or too new for koko."),
+        ),
+        Err(err) => Outcome::Fail(
+            format!("cannot read the setting: {}", err),
+            String::from("Fix the connection problems above first."),
+        ),
+    }
+}
+
+// Has Kodi fetch a file from a temporary server, the same way it fetches the files
+// streamed in a session
+async fn check_reach_back(
+    session: &mut kodi_rpc::WsJsonRPCSession,
+    kodi_address: IpAddr,
+    local_ip: IpAddr,
+    listen_port: u16,
+) -> Outcome {
+    let probe = std::env::temp_dir().join(format!("{}-{}", PROBE_NAME, std::process::id()));
+    if let Err(err) = std::fs::write(&probe, "koko\n") {
+        return Outcome::Fail(
+            format!("cannot create {}: {}", probe.to_string_lossy(), err),
+            String::from("Check that the temporary directory is writable."),
+        );
+    }
+    let app_data = server::make_app_data_holder(server::AppData {
+        kodi_address,
+        ip_access_control: true,
+        kodi_auth: None,
+        files: vec![(String::from(PROBE_NAME), probe.clone())]
+            .into_iter()
+            .collect(),
+        urls_order: HashMap::new(),
+        urls_start: HashMap::new(),
+        background_audio: None,
+        previously_logged_file: None,
+    });
+    let outcome = serve_probe(session, app_data, local_ip, listen_port).await;
+    let _ = std::fs::remove_file(&probe);
+    outcome
+}
+
+async fn serve_probe(
+    session: &mut kodi_rpc::WsJsonRPCSession,
+    app_data: actix_web::web::Data<std::sync::Mutex<server::AppData>>,
+    local_ip: IpAddr,
+    listen_port: u16,
+) -> Outcome {
+    let server = {
+        let app_data = app_data.clone();
+        HttpServer::new(move || {
+            let app_data = app_data.clone();
+            App::new().configure(move |cfg| server::configure(cfg, app_data))
+        })
+        .workers(1)
+    };
+    let server = match server.bind((local_ip, listen_port)) {
+        Ok(server) => server,
+        Err(err) => {
+            return Outcome::Fail(
+                format!("cannot listen on {}:{}: {}", local_ip, listen_port, err),
+                String::from("Pick another port with --listen or listen_port in the config file."),
+            )
+        }
+    };
+    let url = format!("http://{}/file/{}", server.addrs()[0], PROBE_NAME);
+    let result = tokio::select! {
+        done = server.run() => Err(error::Error::MsgError(format!("the server stopped: {:?}", done))),
+        result = with_timeout(kodi_rpc::files_get_file_details(session, &url)) => result,
+    };
+    let fetched = app_data.lock().unwrap().previously_logged_file.is_some();
+    match (fetched, result) {
+        (true, _) => Outcome::Ok(format!("Kodi fetched {}", url)),
+        (false, result) => Outcome::Fail(
+            match result {
+                Ok(_) => format!("Kodi did not fetch {}", url),
+                Err(err) => format!("Kodi could not fetch {}: {}", url, err),
+            },
+            String::from(
+                "Allow incoming connections to koko in this machine's firewall; --listen (or listen_port in the config file) keeps the port the same between runs. If Kodi connects from another address, e.g. through NAT, use --public.",
+            ),
+        ),
+    }
+}
+
+// Checks step by step that a session could work, printing the results; returns whether
+// everything was fine
+pub async fn run(options: Options) -> bool {
+    let mut ok = true;
+
+    let (outcome, kodi_address) = resolve(&options.hostname).await;
+    ok &= report("Host name", &outcome);
+
+    let urls = kodi_address.map(|address| {
+        (
+            Url::parse(format!("http://{}:{}/jsonrpc", address, options.kodi_port).as_str())
+                .expect("Failed to create HTTP URL"),
+            Url::parse(format!("ws://{}:{}/jsonrpc", address, options.kodi_ws_port).as_str())
+                .expect("Failed to create WebSocket URL"),
+        )
+    });
+
+    let (outcome, local_ip) = match &urls {
+        None => (Outcome::Skip, None),
+        Some((url, _)) => check_http(url, &options.auth).await,
+    };
+    ok &= report("HTTP JSON-RPC and authentication", &outcome);
+
+    let (outcome, mut session) = match &urls {
+        None => (Outcome::Skip, None),
+        Some((_, wsurl)) => check_ws(wsurl).await,
+    };
+    ok &= report("WebSocket", &outcome);
+
+    // limitations are reported, but a session works anyway
+    if let Some(session) = &mut session {
+        kodi_rpc::detect_capabilities(session).await;
+        report("JSON-RPC API", &check_capabilities(session.capabilities()));
+    }
+
+    for (setting, label) in SETTINGS {
+        let outcome = match (&urls, kodi_address) {
+            (Some((url, _)), Some(kodi_address)) if session.is_some() || local_ip.is_some() => {
+                let mut http_url = url.clone();
+                if let Some((user, pass)) = &options.auth {
+                    let _ = http_url.set_username(user);
+                    let _ = http_url.set_password(Some(pass));
+                }
+                check_setting(
+                    &mut session,
+                    &http_url,
+                    setting,
+                    label,
+                    kodi_address.is_loopback(),
+                )
+                .await
+            }
+            _ => Outcome::Skip,
+        };
+        ok &= report(&format!("Setting \"{}\"", label), &outcome);
+    }
+
+    // like a session, this works over HTTP too
+    if session.is_none() && local_ip.is_some() {
+        if let Some((url, _)) = &urls {
+            session = with_timeout(kodi_rpc::connect_http(url, &options.auth))
+                .await
+                .ok();
+        }
+    }
+    let outcome = match (&mut session, kodi_address, local_ip) {
+        (Some(session), Some(kodi_address), Some(local_ip)) => {
+            check_reach_back(session, kodi_address, local_ip, options.listen_port).await
+        }
+        _ => Outcome::Skip,
+    };
+    ok &= report("Kodi reaching koko's file server", &outcome);
+
+    println!();
+    if ok {
+        println!("Everything looks fine.");
+    } else {
+        println!("Some checks failed; see the fixes above.");
+    }
+    ok
+}
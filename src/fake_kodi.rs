@@ -0,0 +1,230 @@
+// A stand-in for Kodi's WebSocket JSON-RPC that replays a recording made with --record:
+// each call gets the answer recorded for it, followed by the notifications that came after
+// it. This lets the session logic run without a real Kodi, e.g. in tests.
+use crate::recording::Entry;
+
+use crate::ws_server;
+
+use futures::channel::mpsc;
+use serde_json::Value;
+use url::Url;
+
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub struct Replay {
+    // with whether each has been replayed yet
+    entries: Vec<(Entry, bool)>,
+    // calls that weren't recorded, as "Method params"
+    unmatched: Vec<String>,
+}
+
+impl Replay {
+    pub fn new(entries: Vec<Entry>) -> Replay {
+        Replay {
+            entries: entries.into_iter().map(|entry| (entry, false)).collect(),
+            unmatched: Vec::new(),
+        }
+    }
+
+    // the notifications from index on, up to the next call
+    fn notifications_from(&mut self, index: usize) -> Vec<Value> {
+        let mut notifications = Vec::new();
+        for (entry, replayed) in self.entries.iter_mut().skip(index) {
+            match entry {
+                Entry::Call { .. } => break,
+                Entry::Notification(notification) => {
+                    if !*replayed {
+                        *replayed = true;
+                        notifications.push(notification.clone());
+                    }
+                }
+            }
+        }
+        notifications
+    }
+
+    // the notifications recorded before the first call
+    pub fn initial(&mut self) -> Vec<Value> {
+        self.notifications_from(0)
+    }
+
+    // The answer to a call and the notifications to send after it. The first call not yet
+    // replayed with the same method and parameters is used, or failing that with the same
+    // method, so calls made in a slightly different order still get their answers.
+    pub fn respond(&mut self, method: &str, params: &Option<Value>) -> (Value, Vec<Value>) {
+        let unreplayed = |entries: &[(Entry, bool)], same_params: bool| {
+            entries.iter().position(|(entry, replayed)| match entry {
+                Entry::Call {
+                    method: recorded_method,
+                    params: recorded_params,
+                    ..
+                } => {
+                    !*replayed
+                        && recorded_method == method
+                        && (!same_params || recorded_params == params)
+                }
+                Entry::Notification(_) => false,
+            })
+        };
+        let index = unreplayed(&self.entries, true).or_else(|| unreplayed(&self.entries, false));
+        match index {
+            Some(index) => {
+                self.entries[index].1 = true;
+                let response = match &self.entries[index].0 {
+                    Entry::Call {
+                        error: Some(error), ..
+                    } => serde_json::json!({ "error": error }),
+                    Entry::Call { result, .. } => serde_json::json!({ "result": result }),
+                    Entry::Notification(_) => unreachable!(),
+                };
+                (response, self.notifications_from(index + 1))
+            }
+            // connecting pings before there's anything to record it
+            None if method == "JSONRPC.Ping" => (serde_json::json!({ "result": "pong" }), vec![]),
+            None => {
+                self.unmatched.push(match params {
+                    Some(params) => format!("{} {}", method, params),
+                    None => String::from(method),
+                });
+                let error = serde_json::json!({ "code": -32601, "message": "Method not found." });
+                (serde_json::json!({ "error": error }), vec![])
+            }
+        }
+    }
+
+    pub fn unmatched(&self) -> Vec<String> {
+        self.unmatched.clone()
+    }
+
+    // the recorded calls that haven't been made
+    pub fn remaining(&self) -> Vec<String> {
+        self.entries
+            .iter()
+            .filter_map(|(entry, replayed)| match entry {
+                Entry::Call { method, .. } if !*replayed => Some(method.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct Shared {
+    replay: Mutex<Replay>,
+    // the WebSocket connections, for notifications
+    listeners: Mutex<Vec<mpsc::UnboundedSender<Value>>>,
+}
+
+impl ws_server::Handler for Shared {
+    fn connect(self: &Arc<Self>) -> mpsc::UnboundedReceiver<Value> {
+        let (listener, notifications) = mpsc::unbounded();
+        for notification in self.replay.lock().unwrap().initial() {
+            let _ = listener.unbounded_send(notification);
+        }
+        self.listeners.lock().unwrap().push(listener);
+        notifications
+    }
+
+    fn respond(self: &Arc<Self>, request: &Value) -> Value {
+        let method = request["method"].as_str().unwrap_or("");
+        let params = request.get("params").filter(|x| !x.is_null()).cloned();
+        let (mut response, notifications) = self.replay.lock().unwrap().respond(method, &params);
+        response["jsonrpc"] = Value::from("2.0");
+        response["id"] = request["id"].clone();
+        self.listeners.lock().unwrap().retain(|listener| {
+            notifications
+                .iter()
+                .all(|notification| listener.unbounded_send(notification.clone()).is_ok())
+        });
+        response
+    }
+}
+
+pub struct FakeKodi {
+    addr: std::net::SocketAddr,
+    shared: Arc<Shared>,
+}
+
+impl FakeKodi {
+    // listens on a free port of localhost until the runtime shuts down
+    pub async fn start(entries: Vec<Entry>) -> Result<FakeKodi, ws_server::Error> {
+        let shared = Arc::new(Shared {
+            replay: Mutex::new(Replay::new(entries)),
+            listeners: Mutex::new(Vec::new()),
+        });
+        let addr = ws_server::start([127, 0, 0, 1].into(), 0, shared.clone()).await?;
+        Ok(FakeKodi { addr, shared })
+    }
+
+    pub fn url(&self) -> Url {
+        Url::parse(format!("ws://{}/jsonrpc", self.addr).as_str()).expect("Invalid URL")
+    }
+
+    pub fn unmatched(&self) -> Vec<String> {
+        self.shared.replay.lock().unwrap().unmatched()
+    }
+
+    pub fn remaining(&self) -> Vec<String> {
+        self.shared.replay.lock().unwrap().remaining()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let call = |method: &str, params: Option<Value>, result: Value| Entry::Call {
+            method: String::from(method),
+            params,
+            result: Some(result),
+            error: None,
+        };
+        let notification =
+            |method: &str| Entry::Notification(serde_json::json!({ "method": method }));
+        let mut replay = Replay::new(vec![
+            notification("System.OnWake"),
+            call("Player.Open", None, Value::from("OK")),
+            notification("Player.OnPlay"),
+            notification("Player.OnAVStart"),
+            call(
+                "Player.GetProperties",
+                Some(serde_json::json!([1])),
+                Value::from(1),
+            ),
+            call(
+                "Player.GetProperties",
+                Some(serde_json::json!([2])),
+                Value::from(2),
+            ),
+            notification("Player.OnStop"),
+        ]);
+        assert_eq!(replay.initial().len(), 1);
+        let (response, notifications) = replay.respond("Player.Open", &None);
+        assert_eq!(response, serde_json::json!({ "result": "OK" }));
+        assert_eq!(notifications.len(), 2);
+        // out of order, matched by the parameters
+        let (response, notifications) =
+            replay.respond("Player.GetProperties", &Some(serde_json::json!([2])));
+        assert_eq!(response["result"], 2);
+        assert_eq!(
+            notifications,
+            vec![serde_json::json!({ "method": "Player.OnStop" })]
+        );
+        assert_eq!(
+            replay.remaining(),
+            vec![String::from("Player.GetProperties")]
+        );
+        // then by the method only
+        let (response, _) = replay.respond("Player.GetProperties", &Some(serde_json::json!([3])));
+        assert_eq!(response["result"], 1);
+        assert!(replay.remaining().is_empty());
+
+        let (response, _) = replay.respond("JSONRPC.Ping", &None);
+        assert_eq!(response["result"], "pong");
+        let (response, _) = replay.respond("Player.Stop", &None);
+        assert_eq!(response["error"]["code"], -32601);
+        assert_eq!(replay.unmatched(), vec![String::from("Player.Stop")]);
+    }
+}
@@ -0,0 +1,292 @@
+// What was played where and when: one JSON object per streaming session in history.jsonl
+// in the data directory, appended when the session ends
+use crate::{jsonl, kodi_rpc_types::GlobalTime, position::Position};
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, SecondsFormat};
+use directories::ProjectDirs;
+use serde::{Deserialize, Serialize};
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    JsonlError(#[from] jsonl::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Item {
+    pub file: PathBuf,
+    // how far it got, from the last player properties seen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_ms: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_ms: Option<u32>,
+    // played to the end
+    #[serde(default)]
+    pub finished: bool,
+}
+
+impl Item {
+    // where to continue from when replaying; None for the beginning
+    pub fn resume_position(&self) -> Option<Position> {
+        match self.time_ms {
+            Some(ms) if ms > 0 && !self.finished => Some(Position::Milliseconds(ms)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    // played to the end
+    Finished,
+    // ctrl-c, or quitting the UI
+    Interrupted,
+    Error(String),
+}
+
+impl std::fmt::Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Finished => write!(f, "finished"),
+            Outcome::Interrupted => write!(f, "interrupted"),
+            Outcome::Error(error) => write!(f, "error: {}", error),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    // as given with -k, or the host from the config
+    pub host: String,
+    // RFC 3339
+    pub start: String,
+    pub end: String,
+    pub items: Vec<Item>,
+    pub outcome: Outcome,
+}
+
+impl Session {
+    pub fn start_time(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(&self.start).ok()
+    }
+}
+
+fn now() -> String {
+    Local::now().to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+pub fn default_file() -> Option<PathBuf> {
+    let proj_dirs = ProjectDirs::from("", "Erkki Seppälä", "koko")?;
+    Some(proj_dirs.data_dir().join("history.jsonl"))
+}
+
+// there being no history yet is fine
+pub fn load(path: &Path) -> Result<Vec<Session>, Error> {
+    match jsonl::load(path) {
+        Err(jsonl::Error::IOError(err)) if err.kind() == std::io::ErrorKind::NotFound => {
+            Ok(Vec::new())
+        }
+        result => Ok(result?),
+    }
+}
+
+pub fn append(path: &Path, session: &Session) -> Result<(), Error> {
+    Ok(jsonl::Writer::append(path)?.write(session)?)
+}
+
+// Which sessions koko history lists
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    pub host: Option<String>,
+    // part of the path of any of the files
+    pub file: Option<String>,
+    pub since: Option<NaiveDate>,
+}
+
+impl Filter {
+    pub fn matches(&self, session: &Session) -> bool {
+        let host = match &self.host {
+            Some(host) => &session.host == host,
+            None => true,
+        };
+        let file = match &self.file {
+            Some(file) => session
+                .items
+                .iter()
+                .any(|item| item.file.to_string_lossy().contains(file.as_str())),
+            None => true,
+        };
+        let since = match self.since {
+            Some(since) => session
+                .start_time()
+                .map_or(false, |start| start.naive_local().date() >= since),
+            None => true,
+        };
+        host && file && since
+    }
+}
+
+// relative paths are relative to where koko was run, which a replay needn't be; a file that
+// can't be found is kept as given
+fn absolute(file: PathBuf) -> PathBuf {
+    std::fs::canonicalize(&file).unwrap_or(file)
+}
+
+// Follows a session as it goes and appends it to the history when it ends
+#[derive(Debug)]
+pub struct Recorder {
+    path: PathBuf,
+    session: Session,
+}
+
+impl Recorder {
+    // files are in the order of the playlist
+    pub fn new(path: PathBuf, host: String, files: Vec<PathBuf>) -> Recorder {
+        Recorder {
+            path,
+            session: Session {
+                host,
+                start: now(),
+                end: String::new(),
+                items: files
+                    .into_iter()
+                    .map(|file| Item {
+                        file: absolute(file),
+                        time_ms: None,
+                        total_ms: None,
+                        finished: false,
+                    })
+                    .collect(),
+                outcome: Outcome::Finished,
+            },
+        }
+    }
+
+    // a file added to the end of the playlist
+    pub fn add(&mut self, file: PathBuf) {
+        self.session.items.push(Item {
+            file: absolute(file),
+            time_ms: None,
+            total_ms: None,
+            finished: false,
+        });
+    }
+
+    pub fn progress(&mut self, index: usize, time: Option<GlobalTime>, total: Option<GlobalTime>) {
+        if let Some(item) = self.session.items.get_mut(index) {
+            if let Some(time) = time {
+                item.time_ms = Some(time.as_milliseconds());
+            }
+            if let Some(total) = total.filter(|total| total.as_milliseconds() > 0) {
+                item.total_ms = Some(total.as_milliseconds());
+            }
+        }
+    }
+
+    pub fn finished(&mut self, index: usize) {
+        if let Some(item) = self.session.items.get_mut(index) {
+            item.finished = true;
+        }
+    }
+
+    pub fn interrupted(&mut self) {
+        self.session.outcome = Outcome::Interrupted;
+    }
+
+    pub fn finish<E: std::fmt::Display>(mut self, result: &Result<(), E>) {
+        self.session.end = now();
+        if let Err(err) = result {
+            self.session.outcome = Outcome::Error(err.to_string());
+        }
+        match append(&self.path, &self.session) {
+            Ok(()) => log::info!("Added the session to {}", self.path.to_string_lossy()),
+            Err(err) => log::error!("Failed to write history: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history() {
+        let path = std::env::temp_dir().join(format!("koko-history-{}.jsonl", std::process::id()));
+        let mut recorder = Recorder::new(
+            path.clone(),
+            String::from("tv"),
+            vec![
+                PathBuf::from("/videos/a.mkv"),
+                PathBuf::from("/videos/b.mkv"),
+            ],
+        );
+        recorder.progress(0, Some(GlobalTime::from_seconds(600)), None);
+        recorder.finished(0);
+        recorder.progress(
+            1,
+            Some(GlobalTime::from_seconds(62)),
+            Some(GlobalTime::from_seconds(3600)),
+        );
+        recorder.interrupted();
+        recorder.finish::<String>(&Ok(()));
+        Recorder::new(path.clone(), String::from("laptop"), vec![])
+            .finish(&Err(String::from("Kodi went away")));
+
+        let sessions = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(sessions.len(), 2);
+        let session = &sessions[0];
+        assert_eq!(session.outcome, Outcome::Interrupted);
+        assert_eq!(session.items[0].resume_position(), None);
+        assert_eq!(
+            session.items[1].resume_position(),
+            Some(Position::Milliseconds(62000))
+        );
+        assert_eq!(session.items[1].total_ms, Some(3600000));
+        assert_eq!(
+            sessions[1].outcome,
+            Outcome::Error(String::from("Kodi went away"))
+        );
+
+        let today = session.start_time().unwrap().naive_local().date();
+        let filter = |host: Option<&str>, file: Option<&str>, since: Option<NaiveDate>| Filter {
+            host: host.map(String::from),
+            file: file.map(String::from),
+            since,
+        };
+        assert!(filter(Some("tv"), Some("b.mkv"), Some(today)).matches(session));
+        assert!(!filter(Some("laptop"), None, None).matches(session));
+        assert!(!filter(None, Some("c.mkv"), None).matches(session));
+        assert!(!filter(None, None, today.succ_opt()).matches(session));
+    }
+
+    #[test]
+    fn test_relative_files() {
+        let dir = std::env::temp_dir().join(format!("koko-history-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("a.mkv"), b"").unwrap();
+        let path = dir.join("history.jsonl");
+        let cwd = std::env::current_dir().unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+        Recorder::new(
+            path.clone(),
+            String::from("tv"),
+            vec![PathBuf::from("a.mkv"), PathBuf::from("gone.mkv")],
+        )
+        .finish::<String>(&Ok(()));
+        std::env::set_current_dir(&cwd).unwrap();
+
+        // replayed from elsewhere
+        let sessions = load(&path).unwrap();
+        let items = &sessions[0].items;
+        assert!(items[0].file.is_absolute());
+        assert!(items[0].file.is_file());
+        assert_eq!(items[1].file, PathBuf::from("gone.mkv"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,73 @@
+// Files of one JSON object per line, like recordings and the history
+use serde::{de::DeserializeOwned, Serialize};
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+
+    #[error("Invalid line {} in {}: {}", .1, .0.to_string_lossy(), .2)]
+    ParseError(PathBuf, usize, serde_json::Error),
+}
+
+// empty lines are skipped
+pub fn load<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, Error> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let mut values = Vec::new();
+    for (index, line) in file.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        values.push(
+            serde_json::from_str(&line)
+                .map_err(|err| Error::ParseError(path.to_path_buf(), index + 1, err))?,
+        );
+    }
+    Ok(values)
+}
+
+// Writes a line at a time, flushing each so that what was written survives a crash
+#[derive(Debug)]
+pub struct Writer {
+    file: Mutex<std::io::BufWriter<std::fs::File>>,
+}
+
+impl Writer {
+    // replaces what the file had
+    pub fn create(path: &Path) -> Result<Writer, Error> {
+        Ok(Writer::new(std::fs::File::create(path)?))
+    }
+
+    // keeps what the file had, creating it and its directory if need be
+    pub fn append(path: &Path) -> Result<Writer, Error> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        Ok(Writer::new(file))
+    }
+
+    fn new(file: std::fs::File) -> Writer {
+        Writer {
+            file: Mutex::new(std::io::BufWriter::new(file)),
+        }
+    }
+
+    pub fn write<T: Serialize>(&self, value: &T) -> Result<(), Error> {
+        let line = serde_json::to_string(value).expect("Failed to serialize a line");
+        let mut file = self.file.lock().unwrap();
+        writeln!(file, "{}", line)?;
+        file.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{kodi_rpc_types::Step, position::Position};
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Invalid key name: {}", .0)]
+    InvalidKey(String),
+
+    #[error("Invalid action for key {}: {}", .0, .1)]
+    InvalidAction(String, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Left,
+    Right,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Esc,
+    Tab,
+    Backspace,
+    Del,
+    F(u8),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Quit,
+    PlayPause,
+    Next,
+    Previous,
+    Step(Step),
+    // relative, in seconds
+    Seek(i32),
+    // opens the seek prompt with the key as the first input; only for digits and '-'
+    SeekPrompt,
+    NextChapter,
+    PreviousChapter,
+    Chapters,
+    Repeat,
+    Shuffle,
+    AbLoop,
+    // forwards keys to Kodi's menus until left with Esc
+    Passthrough,
+    // raw JSON-RPC calls
+    Console,
+    Help,
+    // removes a default binding
+    None,
+}
+
+const NAMED_KEYS: &[(&str, Key)] = &[
+    ("space", Key::Char(' ')),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("enter", Key::Enter),
+    ("esc", Key::Esc),
+    ("tab", Key::Tab),
+    ("backspace", Key::Backspace),
+    ("del", Key::Del),
+];
+
+const NAMED_ACTIONS: &[(&str, Action)] = &[
+    ("quit", Action::Quit),
+    ("play-pause", Action::PlayPause),
+    ("next", Action::Next),
+    ("previous", Action::Previous),
+    ("step-small-forward", Action::Step(Step::SmallForward)),
+    ("step-small-backward", Action::Step(Step::SmallBackward)),
+    ("step-big-forward", Action::Step(Step::BigForward)),
+    ("step-big-backward", Action::Step(Step::BigBackward)),
+    ("seek-prompt", Action::SeekPrompt),
+    ("next-chapter", Action::NextChapter),
+    ("previous-chapter", Action::PreviousChapter),
+    ("chapters", Action::Chapters),
+    ("repeat", Action::Repeat),
+    ("shuffle", Action::Shuffle),
+    ("ab-loop", Action::AbLoop),
+    ("passthrough", Action::Passthrough),
+    ("console", Action::Console),
+    ("help", Action::Help),
+    ("none", Action::None),
+];
+
+impl Key {
+    // keys the UI's own views act on: the arrows and Tab move the focus and the seek bar's
+    // cursor, Enter and space press buttons and Esc cancels a seek
+    pub fn used_by_views(&self) -> bool {
+        matches!(
+            self,
+            Key::Left
+                | Key::Right
+                | Key::Up
+                | Key::Down
+                | Key::Enter
+                | Key::Tab
+                | Key::Esc
+                | Key::Char(' ')
+        )
+    }
+}
+
+impl std::str::FromStr for Key {
+    type Err = Error;
+
+    fn from_str(str: &str) -> Result<Key, Error> {
+        let lower = str.to_lowercase();
+        if let Some((_, key)) = NAMED_KEYS.iter().find(|(name, _)| *name == lower) {
+            return Ok(*key);
+        }
+        let mut chars = str.chars();
+        match (chars.next(), chars.next()) {
+            (Some(char), None) => Ok(Key::Char(char)),
+            _ => match lower.strip_prefix('f').map(|n| n.parse::<u8>()) {
+                Some(Ok(n)) if (1..=12).contains(&n) => Ok(Key::F(n)),
+                _ => Err(Error::InvalidKey(String::from(str))),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match NAMED_KEYS.iter().find(|(_, key)| key == self) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => match self {
+                Key::Char(char) => write!(f, "{}", char),
+                Key::F(n) => write!(f, "f{}", n),
+                other => write!(f, "{:?}", other),
+            },
+        }
+    }
+}
+
+impl Action {
+    fn parse(key: &Key, str: &str) -> Result<Action, Error> {
+        let error =
+            |message: &str| Error::InvalidAction(key.to_string(), format!("{}: {}", str, message));
+        let action = match str.strip_prefix("seek ") {
+            // seek +10s, seek -1m30s
+            Some(amount) => {
+                let amount = amount.trim();
+                let (sign, amount) = match amount.strip_prefix('-') {
+                    Some(amount) => (-1, amount),
+                    None => (1, amount.strip_prefix('+').unwrap_or(amount)),
+                };
+                match amount.parse::<Position>() {
+                    Ok(Position::Milliseconds(ms)) if ms >= 1000 => {
+                        Action::Seek(sign * (ms / 1000) as i32)
+                    }
+                    Ok(_) => return Err(error("expected at least a second, like +10s")),
+                    Err(err) => return Err(error(&err.to_string())),
+                }
+            }
+            None => match NAMED_ACTIONS.iter().find(|(name, _)| *name == str) {
+                Some((_, action)) => action.clone(),
+                None => return Err(error("unknown action")),
+            },
+        };
+        match (key, &action) {
+            (Key::Char(char), Action::SeekPrompt) if char.is_ascii_digit() || *char == '-' => {
+                Ok(action)
+            }
+            (_, Action::SeekPrompt) => Err(error("only digits and '-' can open the seek prompt")),
+            _ => Ok(action),
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Seek(seconds) => write!(f, "seek {:+}s", seconds),
+            action => match NAMED_ACTIONS.iter().find(|(_, named)| named == action) {
+                Some((name, _)) => write!(f, "{}", name),
+                None => write!(f, "{:?}", action),
+            },
+        }
+    }
+}
+
+// The key bindings of the UI, in the order they're shown in the help
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    pub bindings: Vec<(Key, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut bindings = vec![
+            (Key::Char(' '), Action::PlayPause),
+            (Key::Char('<'), Action::Step(Step::BigBackward)),
+            (Key::Char(','), Action::Step(Step::SmallBackward)),
+            (Key::Char('.'), Action::Step(Step::SmallForward)),
+            (Key::Char('>'), Action::Step(Step::BigForward)),
+            (Key::PageUp, Action::Previous),
+            (Key::Char('['), Action::Previous),
+            (Key::PageDown, Action::Next),
+            (Key::Char(']'), Action::Next),
+            (Key::Char('{'), Action::PreviousChapter),
+            (Key::Char('}'), Action::NextChapter),
+            (Key::Char('c'), Action::Chapters),
+            (Key::Char('r'), Action::Repeat),
+            (Key::Char('s'), Action::Shuffle),
+            (Key::Char('a'), Action::AbLoop),
+            (Key::Char('p'), Action::Passthrough),
+            (Key::Char(':'), Action::Console),
+            (Key::Char('?'), Action::Help),
+            (Key::Char('q'), Action::Quit),
+        ];
+        bindings.extend(
+            "-0123456789"
+                .chars()
+                .map(|char| (Key::Char(char), Action::SeekPrompt)),
+        );
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    // the defaults, with the given bindings replacing or adding to them
+    pub fn new(keys: &BTreeMap<String, String>) -> Result<Keymap, Error> {
+        let mut keymap = Keymap::default();
+        for (key, action) in keys {
+            let key = key.parse::<Key>()?;
+            let action = Action::parse(&key, action.trim())?;
+            keymap.bindings.retain(|(bound, _)| *bound != key);
+            if action != Action::None {
+                keymap.bindings.push((key, action));
+            }
+        }
+        Ok(keymap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keymap() {
+        let keys = vec![
+            ("right", "seek +10s"),
+            ("left", "seek -1m"),
+            ("q", "none"),
+            ("F1", "help"),
+        ]
+        .into_iter()
+        .map(|(key, action)| (String::from(key), String::from(action)))
+        .collect();
+        let keymap = Keymap::new(&keys).unwrap();
+        let find = |key: Key| {
+            keymap
+                .bindings
+                .iter()
+                .find(|(bound, _)| *bound == key)
+                .map(|(_, action)| action.clone())
+        };
+        assert_eq!(find(Key::Right), Some(Action::Seek(10)));
+        assert_eq!(find(Key::Left), Some(Action::Seek(-60)));
+        assert_eq!(find(Key::Char('q')), None);
+        assert_eq!(find(Key::F(1)), Some(Action::Help));
+        assert_eq!(find(Key::Char(' ')), Some(Action::PlayPause));
+
+        let bad = |key: &str, action: &str| {
+            let keys = vec![(String::from(key), String::from(action))]
+                .into_iter()
+                .collect();
+            Keymap::new(&keys).is_err()
+        };
+        assert!(bad("x", "fly"));
+        assert!(bad("x", "seek-prompt"));
+        assert!(bad("x", "seek 50%"));
+        assert!(bad("ctrl-x", "quit"));
+    }
+}
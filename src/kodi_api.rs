@@ -0,0 +1,68 @@
+// Kodi's JSON-RPC API generated by build.rs from schema/introspect.json; see codegen/mod.rs
+// for how the schema maps to Rust. To pick up a newer Kodi's API, replace the schema with
+// the result of its JSONRPC.Introspect.
+#![allow(clippy::large_enum_variant, clippy::enum_variant_names)]
+
+use crate::error::Error;
+use crate::kodi_rpc::{request, WsJsonRPCSession, NO_PARAMS};
+
+use serde::{Deserialize, Serialize};
+
+include!(concat!(env!("OUT_DIR"), "/kodi_api.rs"));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generated() {
+        let params = PlayerSeekParams {
+            playerid: 1,
+            value: PlayerSeekParamsValue::Time(PlayerSeekParamsValueTime {
+                time: PlayerPositionTime {
+                    hours: None,
+                    minutes: Some(2),
+                    seconds: None,
+                    milliseconds: None,
+                },
+            }),
+        };
+        assert_eq!(
+            serde_json::to_value(&params).unwrap(),
+            serde_json::json!({ "playerid": 1, "value": { "time": { "minutes": 2 } } })
+        );
+
+        let value: PlayerPropertyValue = serde_json::from_value(serde_json::json!({
+            "repeat": "all",
+            "time": { "hours": 0, "minutes": 1, "seconds": 2, "milliseconds": 3 },
+            "currentvideostream": {
+                "index": 0, "name": "", "language": "", "codec": "h264", "width": 1920, "height": 1080
+            }
+        }))
+        .unwrap();
+        assert_eq!(value.repeat, Some(PlayerRepeat::All));
+        assert_eq!(value.time.map(|time| time.seconds), Some(2));
+        assert_eq!(
+            value.currentvideostream.map(|stream| stream.codec),
+            Some(String::from("h264"))
+        );
+
+        // fields of the types List.Item.All extends are included
+        let item: ListItemAll =
+            serde_json::from_value(serde_json::json!({ "label": "foo", "type": "movie" })).unwrap();
+        assert_eq!(item.label, "foo");
+        assert_eq!(item.type_, Some(ListItemBaseType::Movie));
+
+        assert_eq!(
+            serde_json::to_value(GUIWindow::Tvguide).unwrap(),
+            serde_json::json!("tvguide")
+        );
+
+        let params: PlayerOnPlayParams = serde_json::from_value(serde_json::json!({
+            "sender": "xbmc",
+            "data": { "item": { "type": "unknown" }, "player": { "playerid": 1, "speed": 1 } }
+        }))
+        .unwrap();
+        assert_eq!(params.data.player.playerid, 1);
+    }
+}
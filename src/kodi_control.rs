@@ -1,9 +1,15 @@
 use async_trait::async_trait;
 
-use crate::{error, exit, kodi_rpc, kodi_rpc_types, util::*};
+use crate::{
+    config, error, exit, history, kodi_api, kodi_rpc, kodi_rpc_types, media::MediaType,
+    position::Position, util::*,
+};
 
 use url::Url;
 
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
 use thiserror::Error;
 
 use tokio::select;
@@ -14,6 +20,8 @@ pub struct ControlContext {
     jsonrpc_session: kodi_rpc::WsJsonRPCSession,
     player_id: kodi_rpc_types::PlayerId,
     kodi_info_callback: Option<Box<dyn KodiInfoCallback>>,
+    // the properties a request got, for the history
+    properties: Option<kodi_rpc_types::PlayerPropertyValue>,
 }
 
 #[async_trait]
@@ -28,6 +36,9 @@ pub trait ControlRequestWrapper: Send + std::fmt::Debug {
 
 pub type KodiControlReceiver = mpsc::Receiver<Box<dyn ControlRequestWrapper + Send>>;
 
+// files to add to the playlist of a running session: the URL it's served at, and the file
+pub type EnqueueReceiver = mpsc::Receiver<(Url, std::path::PathBuf)>;
+
 pub struct KodiControl {
     channel: mpsc::Sender<Box<dyn ControlRequestWrapper + Send>>,
 }
@@ -101,6 +112,7 @@ impl ControlRequest<Option<kodi_rpc_types::PlayerPropertyValue>> for PropertiesR
                 None
             }
         };
+        context.properties = value.clone();
         (context, value)
     }
 }
@@ -178,8 +190,169 @@ impl ControlRequest<()> for PrevRequest {
     }
 }
 
+#[derive(Debug)]
+struct SetRepeatRequest {
+    repeat: kodi_api::PlayerSetRepeatParamsRepeat,
+}
+
+#[async_trait]
+impl ControlRequest<()> for SetRepeatRequest {
+    async fn request(&mut self, mut context: ControlContext) -> (ControlContext, ()) {
+        kodi_api::player_set_repeat(
+            &mut context.jsonrpc_session,
+            kodi_api::PlayerSetRepeatParams {
+                playerid: context.player_id.into(),
+                repeat: self.repeat.clone(),
+            },
+        )
+        .await
+        .expect("TODO failed to set repeat");
+        (context, ())
+    }
+}
+
+#[derive(Debug)]
+struct SetShuffleRequest {
+    shuffle: kodi_api::GlobalToggle,
+}
+
+#[async_trait]
+impl ControlRequest<()> for SetShuffleRequest {
+    async fn request(&mut self, mut context: ControlContext) -> (ControlContext, ()) {
+        kodi_api::player_set_shuffle(
+            &mut context.jsonrpc_session,
+            kodi_api::PlayerSetShuffleParams {
+                playerid: context.player_id.into(),
+                shuffle: self.shuffle.clone(),
+            },
+        )
+        .await
+        .expect("TODO failed to set shuffle");
+        (context, ())
+    }
+}
+
+// Chapters of the current item. Player.GetProperties doesn't know about them, so
+// they come from the info labels.
+#[derive(Debug, Clone, Default)]
+pub struct Chapters {
+    // 1-based, 0 when there are none
+    pub current: u32,
+    // start of each chapter as a percentage of the item
+    pub starts: Vec<f64>,
+}
+
+#[derive(Debug)]
+struct ChaptersRequest {}
+
+#[async_trait]
+impl ControlRequest<Option<Chapters>> for ChaptersRequest {
+    async fn request(&mut self, mut context: ControlContext) -> (ControlContext, Option<Chapters>) {
+        if !context
+            .jsonrpc_session
+            .capabilities()
+            .has_method("XBMC.GetInfoLabels")
+        {
+            return (context, None);
+        }
+        let labels = kodi_api::xbmc_get_info_labels(
+            &mut context.jsonrpc_session,
+            kodi_api::XBMCGetInfoLabelsParams {
+                labels: vec![
+                    String::from("Player.Chapter"),
+                    String::from("Player.ChapterCount"),
+                    String::from("Player.Chapters"),
+                ],
+            },
+        )
+        .await;
+        let labels = match labels {
+            Ok(labels) => labels,
+            Err(err) => {
+                log::error!("Failed to receive chapters: {}", err);
+                return (context, None);
+            }
+        };
+        let label = |name: &str| labels.get(name).map(|x| x.as_str()).unwrap_or("");
+        let count = label("Player.ChapterCount").parse::<usize>().unwrap_or(0);
+        // older Kodis don't have Player.Chapters, in which case there's just the count
+        let starts: Vec<f64> = label("Player.Chapters")
+            .split(',')
+            .filter_map(|start| start.trim().parse::<f64>().ok())
+            .collect();
+        let chapters = Chapters {
+            current: label("Player.Chapter").parse::<u32>().unwrap_or(0),
+            starts: if starts.len() == count {
+                starts
+            } else {
+                Vec::new()
+            },
+        };
+        (context, Some(chapters))
+    }
+}
+
+#[derive(Debug)]
+struct ExecuteActionRequest {
+    action: kodi_api::InputAction,
+}
+
+#[async_trait]
+impl ControlRequest<()> for ExecuteActionRequest {
+    async fn request(&mut self, mut context: ControlContext) -> (ControlContext, ()) {
+        // not every Kodi knows every action
+        let params = kodi_api::InputExecuteActionParams {
+            action: self.action,
+        };
+        match kodi_api::input_execute_action(&mut context.jsonrpc_session, params).await {
+            Ok(_) => (),
+            Err(err) => log::error!("Failed to execute action {:?}: {}", self.action, err),
+        }
+        (context, ())
+    }
+}
+
+#[derive(Debug)]
+struct InputButtonRequest {
+    button: kodi_rpc_types::InputButton,
+}
+
+#[async_trait]
+impl ControlRequest<()> for InputButtonRequest {
+    async fn request(&mut self, mut context: ControlContext) -> (ControlContext, ()) {
+        // e.g. Input.Back with nothing to go back to fails; that's not worth more than a log
+        match kodi_rpc::input_button(&mut context.jsonrpc_session, self.button).await {
+            Ok(_) => (),
+            Err(err) => log::error!("Failed to send {}: {}", self.button.method(), err),
+        }
+        (context, ())
+    }
+}
+
+#[derive(Debug)]
+struct InputSendTextRequest {
+    text: String,
+    done: bool,
+}
+
+#[async_trait]
+impl ControlRequest<()> for InputSendTextRequest {
+    async fn request(&mut self, mut context: ControlContext) -> (ControlContext, ()) {
+        match kodi_rpc::input_send_text(&mut context.jsonrpc_session, &self.text, self.done).await {
+            Ok(_) => (),
+            Err(err) => log::error!("Failed to send text: {}", err),
+        }
+        (context, ())
+    }
+}
+
 pub trait KodiInfoCallback: Send + std::fmt::Debug {
+    // called as each item starts, with its position if it's in a playlist
     fn playlist_position(&mut self, position: Option<kodi_rpc_types::PlaylistPosition>);
+    // what doesn't work with this Kodi, if anything; called once the callback is set
+    fn limitations(&mut self, limitations: Vec<String>);
+    // every notification from Kodi as it was received, including the ones we don't parse
+    fn notification(&mut self, _notification: &serde_json::Value) {}
 }
 
 #[derive(Debug)]
@@ -187,6 +360,7 @@ struct DefaultKodiInfoCallback {}
 
 impl KodiInfoCallback for DefaultKodiInfoCallback {
     fn playlist_position(&mut self, _position: Option<kodi_rpc_types::PlaylistPosition>) {}
+    fn limitations(&mut self, _limitations: Vec<String>) {}
 }
 
 #[derive(Debug)]
@@ -198,10 +372,40 @@ struct SetCallbackRequest {
 impl ControlRequest<()> for SetCallbackRequest {
     async fn request(&mut self, mut context: ControlContext) -> (ControlContext, ()) {
         context.kodi_info_callback = self.kodi_info_callback.take();
+        let limitations = context.jsonrpc_session.limitations();
+        if let Some(callback) = context.kodi_info_callback.as_mut() {
+            callback.limitations(limitations);
+        }
         (context, ())
     }
 }
 
+// An arbitrary JSON-RPC call, for the console
+#[derive(Debug)]
+struct RpcRequest {
+    method: String,
+    params: Option<serde_json::Value>,
+}
+
+#[async_trait]
+impl ControlRequest<Result<serde_json::Value, String>> for RpcRequest {
+    async fn request(
+        &mut self,
+        mut context: ControlContext,
+    ) -> (ControlContext, Result<serde_json::Value, String>) {
+        let result = kodi_rpc::call(
+            &mut context.jsonrpc_session,
+            &self.method,
+            self.params.take(),
+        )
+        .await;
+        (
+            context,
+            result.map_err(|err| kodi_rpc::describe_error(&err)),
+        )
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("TrySendError in KodiControl: {}", .0)]
@@ -220,6 +424,42 @@ impl KodiControl {
     pub fn play_pause(&mut self) -> Result<(), Error> {
         self.sync_request(Box::new(PlayPauseRequest {}))
     }
+    pub fn set_repeat(
+        &mut self,
+        repeat: kodi_api::PlayerSetRepeatParamsRepeat,
+    ) -> Result<(), Error> {
+        self.sync_request(Box::new(SetRepeatRequest { repeat }))
+    }
+    pub fn set_shuffle(&mut self, shuffle: kodi_api::GlobalToggle) -> Result<(), Error> {
+        self.sync_request(Box::new(SetShuffleRequest { shuffle }))
+    }
+    pub fn chapters(&mut self) -> Result<Option<Chapters>, Error> {
+        self.sync_request(Box::new(ChaptersRequest {}))
+    }
+    pub fn execute_action(&mut self, action: kodi_api::InputAction) -> Result<(), Error> {
+        self.sync_request(Box::new(ExecuteActionRequest { action }))
+    }
+    // the Input.* family; these don't wait for Kodi so holding a key down stays responsive
+    pub fn input(&mut self, button: kodi_rpc_types::InputButton) -> Result<(), Error> {
+        self.async_request(Box::new(InputButtonRequest { button }))
+    }
+    pub fn input_send_text(&mut self, text: &str, done: bool) -> Result<(), Error> {
+        self.async_request(Box::new(InputSendTextRequest {
+            text: String::from(text),
+            done,
+        }))
+    }
+    // the result, or the error as text
+    pub fn rpc(
+        &mut self,
+        method: &str,
+        params: Option<serde_json::Value>,
+    ) -> Result<Result<serde_json::Value, String>, Error> {
+        self.sync_request(Box::new(RpcRequest {
+            method: String::from(method),
+            params,
+        }))
+    }
     pub fn set_callback(
         &mut self,
         kodi_info_callback: Box<dyn KodiInfoCallback>,
@@ -284,39 +524,223 @@ impl KodiControl {
     }
 }
 
+// Shows a notification from a template of [notifications], if it's configured. A failed
+// notification isn't worth ending the session over.
+async fn notify(
+    jsonrpc_session: &mut kodi_rpc::WsJsonRPCSession,
+    notifications: &config::Notifications,
+    template: &Option<String>,
+    image: kodi_rpc_types::GUINotificationImage,
+    values: &[(&str, String)],
+) {
+    let template = match template {
+        None => return,
+        Some(template) => template,
+    };
+    if !jsonrpc_session
+        .capabilities()
+        .has_method("GUI.ShowNotification")
+    {
+        return;
+    }
+    let params = kodi_rpc_types::GUIShowNotificationParams {
+        title: notifications.title(),
+        message: config::fill_template(template, values),
+        image: Some(image),
+        displaytime: notifications.display_time.map(|seconds| seconds * 1000),
+    };
+    match kodi_rpc::gui_show_notification(jsonrpc_session, params).await {
+        Ok(_) => (),
+        Err(err) => log::error!("Failed to show notification: {}", err),
+    }
+}
+
+// Kodi state to put back the way it was when the session ends
+#[derive(Default)]
+struct Restore {
+    window: Option<kodi_rpc_types::GUIWindow>,
+    playlist: Vec<kodi_rpc_types::PlaylistItem>,
+}
+
+// Kodi settings changed for the session, with their previous values
+type Settings = Arc<Mutex<Vec<(String, kodi_api::SettingValueExtended)>>>;
+
+async fn restore_settings(jsonrpc_session: &mut kodi_rpc::WsJsonRPCSession, settings: &Settings) {
+    let settings = std::mem::take(&mut *settings.lock().unwrap());
+    for (setting, value) in settings {
+        log::info!("Restoring setting {} to {:?}", setting, value);
+        let params = kodi_api::SettingsSetSettingValueParams {
+            setting: setting.clone(),
+            value,
+        };
+        if let Err(err) = kodi_api::settings_set_setting_value(jsonrpc_session, params).await {
+            log::error!("Failed to restore setting {}: {}", setting, err);
+        }
+    }
+}
+
 async fn finish(
     jsonrpc_session: &mut kodi_rpc::WsJsonRPCSession,
     player_id: kodi_rpc_types::PlayerId,
     playlist_id: kodi_rpc_types::PlaylistId,
     use_playlist: bool,
+    on_end: config::OnEnd,
+    restore: Restore,
+    settings: &Settings,
+    background_player_id: Option<kodi_rpc_types::PlayerId>,
 ) -> Result<(), error::Error> {
+    // settings are restored even when leaving playback running, so they don't stick around
+    restore_settings(jsonrpc_session, settings).await;
+    if on_end == config::OnEnd::LeavePlaying {
+        log::info!("Leaving playback running");
+        if !restore.playlist.is_empty() {
+            log::info!("Not restoring the previous playlist as it would stop the playback");
+        }
+        return Ok(());
+    }
     kodi_rpc::player_stop(jsonrpc_session, player_id)
         .await
         .expect("TODO failed to stop playersies");
+    match background_player_id {
+        None => (),
+        Some(background_player_id) => {
+            kodi_rpc::player_stop(jsonrpc_session, background_player_id)
+                .await
+                .expect("TODO failed to stop background player");
+        }
+    }
     if use_playlist {
         kodi_rpc::playlist_clear(jsonrpc_session, playlist_id)
             .await
             .expect("TODO failed to clear playlist");
+        if !restore.playlist.is_empty() {
+            log::info!("Restoring {} playlist items", restore.playlist.len());
+            kodi_rpc::playlist_add_items(jsonrpc_session, playlist_id, restore.playlist)
+                .await
+                .expect("TODO failed to restore playlist");
+        }
+    }
+    let window = match on_end {
+        config::OnEnd::StopAndHome => Some(kodi_rpc_types::GUIWindow::Home),
+        // windows we don't know how to return to are replaced with Home
+        config::OnEnd::Restore => Some(restore.window.unwrap_or(kodi_rpc_types::GUIWindow::Home)),
+        config::OnEnd::Stop | config::OnEnd::LeavePlaying => None,
+    };
+    match window {
+        None => (),
+        Some(window) => {
+            kodi_rpc::gui_activate_window(
+                jsonrpc_session,
+                window,
+                vec![String::from("required parameter")],
+            )
+            .await
+            .expect("TODO failed to activate window");
+        }
     }
-    kodi_rpc::gui_activate_window(
-        jsonrpc_session,
-        kodi_rpc_types::GUIWindow::Home,
-        vec![String::from("required parameter")],
-    )
-    .await
-    .expect("TODO failed to go Home");
     Ok(())
 }
 
+pub enum Mode {
+    // Play the given files
+    Play,
+    // Control whatever Kodi is already playing
+    Remote,
+}
+
+impl Mode {
+    // what to do at the end unless configured otherwise: what koko started is stopped, what
+    // was already playing is left alone
+    pub fn default_on_end(&self) -> config::OnEnd {
+        match self {
+            Mode::Play => config::OnEnd::StopAndHome,
+            Mode::Remote => config::OnEnd::LeavePlaying,
+        }
+    }
+}
+
 pub struct Args {
     pub kodi_control_rx: KodiControlReceiver,
-    pub start_seconds: Option<u32>,
+    pub mode: Mode,
+    pub on_end: config::OnEnd,
+    // in remote mode this is taken from the active player instead
+    pub media_type: MediaType,
+    // where each item starts; filled in by the server once the URLs are known
+    pub starts: HashMap<Url, Position>,
+    // where to stop the first item
+    pub end: Option<Position>,
+    pub repeat: Option<kodi_rpc_types::PlayerRepeat>,
+    pub shuffle: bool,
+    pub append: bool,
+    // seconds each picture is shown
+    pub slide_duration: Option<u32>,
+    // played along with pictures; filled in by the server once the URL is known
+    pub background_audio: Option<Url>,
+    pub notifications: config::Notifications,
+    // done after everything else when the session ends
+    pub power: Option<config::Power>,
+    // where to record the JSON-RPC traffic of the session, for replaying with fake_kodi
+    pub record: Option<std::path::PathBuf>,
+    // for the items in the order of urls
+    pub history: Option<history::Recorder>,
+    // with koko watch, more files to play; the session then goes on after the playlist ends
+    pub enqueue_rx: Option<EnqueueReceiver>,
+    // Kodi's JSON-RPC over HTTP and its credentials, for restoring the settings and notifying
+    // of the error after an error took the session down; filled in by the server
+    pub kodi_http: Option<(Url, Option<(String, String)>)>,
+}
+
+impl Args {
+    // the defaults for a video session, to fill in with struct update syntax
+    pub fn new(kodi_control_rx: KodiControlReceiver, mode: Mode) -> Args {
+        Args {
+            kodi_control_rx,
+            on_end: mode.default_on_end(),
+            mode,
+            media_type: MediaType::Video,
+            starts: HashMap::new(),
+            end: None,
+            repeat: None,
+            shuffle: false,
+            append: false,
+            slide_duration: None,
+            background_audio: None,
+            notifications: config::Notifications::default(),
+            power: None,
+            record: None,
+            history: None,
+            enqueue_rx: None,
+            kodi_http: None,
+        }
+    }
+}
+
+// Kodi doesn't always give a URL back encoded the way it was given, so they're compared decoded
+fn find_url(urls: &[Url], file: &str) -> Option<usize> {
+    let decode = |url: &str| {
+        percent_encoding::percent_decode_str(url)
+            .decode_utf8_lossy()
+            .into_owned()
+    };
+    let file = decode(file);
+    urls.iter().position(|url| decode(url.as_str()) == file)
+}
+
+// never resolves without anything to enqueue, so that the session isn't ended by it
+async fn next_enqueued(enqueue_rx: &mut Option<EnqueueReceiver>) -> (Url, std::path::PathBuf) {
+    match enqueue_rx {
+        Some(enqueue_rx) => match enqueue_rx.next().await {
+            Some(enqueued) => enqueued,
+            None => futures::future::pending().await,
+        },
+        None => futures::future::pending().await,
+    }
 }
 
 #[rustfmt::skip::macros(select)]
 pub async fn rpc_handler(
     mut jsonrpc_session: kodi_rpc::WsJsonRPCSession,
-    urls: Vec<Url>,
+    mut urls: Vec<Url>,
     mut sigint_rx: mpsc::Receiver<()>,
     stop_server_tx: tokio::sync::oneshot::Sender<()>,
     rpc_handler_done_tx: tokio::sync::oneshot::Sender<Result<(), error::Error>>,
@@ -324,55 +748,20 @@ pub async fn rpc_handler(
     mut args: Args,
 ) {
     let mut kodi_info_callback: Box<dyn KodiInfoCallback> = Box::new(DefaultKodiInfoCallback {});
-    let mut first_play = true;
+    let history = Arc::new(Mutex::new(args.history.take()));
+    // the history is written once the session has ended, however that happens
+    let history_at_end = history.clone();
+    // as are the settings restored
+    let settings: Settings = Arc::new(Mutex::new(Vec::new()));
+    let settings_at_end = settings.clone();
+    let kodi_http = args.kodi_http.take();
+    // for telling about an error that ended the session
+    let notifications = args.notifications.clone();
     let result = get_errors(async move {
         let mut stream = kodi_rpc::subscribe(&mut jsonrpc_session).await?;
 
         use kodi_rpc_types::*;
 
-        let playlist_id = 1;
-        log::info!("Playing: {:?}", &urls);
-        assert!(urls.len() > 0);
-        let use_playlist = urls.len() > 1;
-        if !use_playlist {
-            let url = &urls[0];
-            let item = PlayerOpenParamsItem::PlaylistItem(PlaylistItem::File {
-                file: url.to_string(),
-            });
-            let player = kodi_rpc::player_open(&mut jsonrpc_session, item).await?;
-            log::debug!("Playing result: {:?}", player);
-        } else {
-            // let items = kodi_rpc::ws_jsonrpc_playlist_get_items(&mut jsonrpc_session, playlist_id).await?;
-            // log::info!("Existing playlist: {:?}", items);
-            kodi_rpc::playlist_clear(&mut jsonrpc_session, playlist_id).await?;
-            let player = kodi_rpc::playlist_add(
-                &mut jsonrpc_session,
-                playlist_id,
-                urls.iter().map(|url| url.to_string()).collect(),
-            )
-            .await?;
-            log::debug!("Enqueued result: {:?}", player);
-
-            let item = PlayerOpenParamsItem::PlaylistPos {
-                playlist_id,
-                position: 0,
-            };
-            let player = kodi_rpc::player_open(&mut jsonrpc_session, item).await?;
-            log::debug!("Playing result: {:?}", player);
-        }
-
-        kodi_rpc::gui_activate_window(
-            &mut jsonrpc_session,
-            GUIWindow::FullscreenVideo,
-            vec![String::from("required parameter")],
-        )
-        .await?;
-
-        let mut player_id = 0u32;
-
-        let mut playlist_position = None;
-        kodi_info_callback.playlist_position(playlist_position);
-
         enum State {
             WaitingStart,
             WaitingTimeout(tokio::time::Instant),
@@ -382,18 +771,239 @@ pub async fn rpc_handler(
         #[derive(Debug)]
         enum Event {
             Notification(Notification),
+            // one we can't parse, only of interest to the callback
+            Unknown,
             SigInt,
             Deadline,
+            Boundary,
             Exit,
             Control(Box<dyn ControlRequestWrapper + Send>),
+            Enqueue(Url, std::path::PathBuf),
         }
 
+        let mut media_type = args.media_type;
+        let playlist_id = media_type.playlist_id();
+        let mut player_id = 0u32;
         let mut state = State::WaitingStart;
+        let mut restore = Restore::default();
+        let mut background_player_id = None;
+        // where our items start in the playlist
+        let mut playlist_offset: PlaylistPosition = 0;
+        let remote = match args.mode {
+            Mode::Play => false,
+            Mode::Remote => true,
+        };
+        let watching = args.enqueue_rx.is_some();
+        // watching, and the playlist has ended; the next file enqueued starts it again
+        let mut idle = false;
+
+        restore.window = match args.on_end {
+            config::OnEnd::Restore => {
+                let gui = kodi_rpc::gui_get_properties(
+                    &mut jsonrpc_session,
+                    vec![GUIPropertyName::CurrentWindow],
+                )
+                .await?;
+                log::debug!("GUI properties: {:?}", gui);
+                gui.current_window
+                    .and_then(|window| GUIWindow::from_id(window.id))
+            }
+            _ => None,
+        };
+
+        let use_playlist = match args.mode {
+            Mode::Play => {
+                log::info!("Playing: {:?}", &urls);
+                assert!(urls.len() > 0);
+
+                if media_type == MediaType::Picture {
+                    let can_set = jsonrpc_session
+                        .capabilities()
+                        .has_method("Settings.SetSettingValue");
+                    match args.slide_duration {
+                        None => (),
+                        Some(_) if !can_set => {
+                            log::warn!("This Kodi cannot set the slide duration")
+                        }
+                        Some(slide_duration) => {
+                            let setting = String::from("slideshow.staytime");
+                            let previous = kodi_api::settings_get_setting_value(
+                                &mut jsonrpc_session,
+                                kodi_api::SettingsGetSettingValueParams {
+                                    setting: setting.clone(),
+                                },
+                            )
+                            .await?;
+                            settings
+                                .lock()
+                                .unwrap()
+                                .push((setting.clone(), previous.value));
+                            kodi_api::settings_set_setting_value(
+                                &mut jsonrpc_session,
+                                kodi_api::SettingsSetSettingValueParams {
+                                    setting,
+                                    value: kodi_api::SettingValueExtended::Integer(
+                                        slide_duration.into(),
+                                    ),
+                                },
+                            )
+                            .await?;
+                        }
+                    }
+                    // the audio is started first, so the slideshow ends up in the front
+                    match &args.background_audio {
+                        None => (),
+                        Some(url) => {
+                            log::info!("Playing background audio: {}", url);
+                            let item = PlayerOpenParamsItem::PlaylistItem(PlaylistItem::File {
+                                file: url.to_string(),
+                            });
+                            let options = PlayerOpenOptions {
+                                shuffled: None,
+                                repeat: Some(PlayerRepeat::One),
+                            };
+                            kodi_rpc::player_open(&mut jsonrpc_session, item, Some(options))
+                                .await?;
+                            // if it's not up yet, its Player.OnPlay tells the id instead
+                            background_player_id =
+                                kodi_rpc::get_active_players(&mut jsonrpc_session)
+                                    .await?
+                                    .iter()
+                                    .find(|player| {
+                                        MediaType::from_player_type(&player.type_)
+                                            == Some(MediaType::Audio)
+                                    })
+                                    .map(|player| player.playerid);
+                        }
+                    }
+                }
+                // repeat only applies to playlists, so even a single file is played via one then
+                let use_playlist =
+                    urls.len() > 1 || args.repeat.is_some() || args.append || watching;
+                if !use_playlist {
+                    let url = &urls[0];
+                    let item = PlayerOpenParamsItem::PlaylistItem(PlaylistItem::File {
+                        file: url.to_string(),
+                    });
+                    let player = kodi_rpc::player_open(&mut jsonrpc_session, item, None).await?;
+                    log::debug!("Playing result: {:?}", player);
+                } else {
+                    let items = if jsonrpc_session
+                        .capabilities()
+                        .has_method("Playlist.GetItems")
+                    {
+                        kodi_rpc::playlist_get_items(
+                            &mut jsonrpc_session,
+                            playlist_id,
+                            vec![ListFieldsAll::File],
+                        )
+                        .await?
+                        .items
+                    } else {
+                        Vec::new()
+                    };
+                    log::info!("Existing playlist: {:?}", items);
+                    restore.playlist = items
+                        .iter()
+                        .filter_map(|item| {
+                            let playlist_item = item.to_playlist_item();
+                            if playlist_item.is_none() {
+                                log::info!("Cannot restore playlist item {:?}", item);
+                            }
+                            playlist_item
+                        })
+                        .collect();
+                    if args.append {
+                        playlist_offset = items.len() as PlaylistPosition;
+                    } else {
+                        kodi_rpc::playlist_clear(&mut jsonrpc_session, playlist_id).await?;
+                    }
+                    let player = kodi_rpc::playlist_add(
+                        &mut jsonrpc_session,
+                        playlist_id,
+                        urls.iter().map(|url| url.to_string()).collect(),
+                    )
+                    .await?;
+                    log::debug!("Enqueued result: {:?}", player);
+
+                    // when appending, our files start after the existing ones
+                    let item = PlayerOpenParamsItem::PlaylistPos {
+                        playlist_id,
+                        position: playlist_offset,
+                    };
+                    // always set both so that modes left over from earlier sessions don't apply
+                    let options = PlayerOpenOptions {
+                        shuffled: Some(args.shuffle),
+                        repeat: Some(args.repeat.unwrap_or(PlayerRepeat::Off)),
+                    };
+                    let player =
+                        kodi_rpc::player_open(&mut jsonrpc_session, item, Some(options)).await?;
+                    log::debug!("Playing result: {:?}", player);
+                }
+
+                kodi_rpc::gui_activate_window(
+                    &mut jsonrpc_session,
+                    media_type.window(),
+                    vec![String::from("required parameter")],
+                )
+                .await?;
+
+                notify(
+                    &mut jsonrpc_session,
+                    &args.notifications,
+                    &args.notifications.start,
+                    GUINotificationImage::Info,
+                    &[("count", urls.len().to_string())],
+                )
+                .await;
+
+                use_playlist
+            }
+            Mode::Remote => {
+                // nothing to open: pick up whatever is already playing
+                let players = kodi_rpc::get_active_players(&mut jsonrpc_session).await?;
+                log::debug!("Active players: {:?}", players);
+                player_id = match players.first() {
+                    Some(player) => {
+                        media_type =
+                            MediaType::from_player_type(&player.type_).unwrap_or(media_type);
+                        player.playerid
+                    }
+                    None => {
+                        return Err(error::Error::MsgError(String::from(
+                            "Kodi is not playing anything to control",
+                        )))
+                    }
+                };
+                state = State::WaitingLast;
+                false
+            }
+        };
+
+        // the position of our last item in the playlist
+        let mut playlist_end = playlist_offset + urls.len() as PlaylistPosition - 1;
+        // when to check whether the item has reached args.end_seconds
+        let mut boundary: Option<tokio::time::Instant> = None;
+
+        let mut playlist_position = None;
+        kodi_info_callback.playlist_position(playlist_position);
+        let keep_history = history.lock().unwrap().is_some();
+        // the index of the playing item in urls, for the history
+        let mut history_index: Option<usize> = None;
+
+        // fixed for the session, and the session moves around below
+        let capabilities = jsonrpc_session.capabilities().clone();
 
         while let Some(notification) = select! {
-            notification = stream.next() => {
+            notification = stream.next_raw() => {
                 match notification {
-                    Some(ev) => Some(Event::Notification(ev)),
+                    Some(kodi_rpc::RawNotification { value, notification }) => {
+                        kodi_info_callback.notification(&value);
+                        match notification {
+                            Some(ev) => Some(Event::Notification(ev)),
+                            None => Some(Event::Unknown),
+                        }
+                    }
                     None => None,
                 }
             }
@@ -405,15 +1015,44 @@ pub async fn rpc_handler(
 		}) => {
                 Some(Event::Deadline)
             }
+            _boundary = tokio::time::sleep_until(boundary.unwrap_or_else(far_future)) => {
+		Some(Event::Boundary)
+            }
             _exit = exit.wait() => {
 		Some(Event::Exit)
             }
             control_request = args.kodi_control_rx.next() => {
 		control_request.map(|x| Event::Control(x))
             }
+            (url, file) = next_enqueued(&mut args.enqueue_rx) => {
+		Some(Event::Enqueue(url, file))
+            }
         } {
             log::debug!("Got notification: {:?}", notification);
 
+            let notification = match notification {
+                // in a slideshow only the picture player counts, not the background audio
+                Event::Notification(Notification::PlayerOnPlay(data))
+                | Event::Notification(Notification::PlayerOnAVStart(data))
+                    if media_type == MediaType::Picture
+                        && !remote
+                        && !matches!(data.data.item, NotificationsItem::Picture { .. }) =>
+                {
+                    log::debug!("Ignoring start of player {}", data.data.player.player_id);
+                    background_player_id.get_or_insert(data.data.player.player_id);
+                    continue;
+                }
+                // the picture player doesn't send AVStart, and neither does an old Kodi, so
+                // this is the best we have
+                Event::Notification(Notification::PlayerOnPlay(data))
+                    if media_type == MediaType::Picture
+                        || !capabilities.has_notification("Player.OnAVStart") =>
+                {
+                    Event::Notification(Notification::PlayerOnAVStart(data))
+                }
+                notification => notification,
+            };
+
             match notification {
                 Event::Notification(Notification::PlayerOnAVStart(data)) => {
                     log::debug!("Cool, proceed");
@@ -421,16 +1060,17 @@ pub async fn rpc_handler(
                         State::WaitingStart => {
                             player_id = data.data.player.player_id;
                         }
+                        // in remote mode the player changes along with the kind of media played
+                        _ if remote => {
+                            player_id = data.data.player.player_id;
+                        }
                         _ => (),
                     }
 
                     let props = kodi_rpc::player_get_properties(
                         &mut jsonrpc_session,
                         player_id,
-                        vec![
-                            PlayerPropertyName::CurrentVideoStream,
-                            PlayerPropertyName::PlaylistPosition,
-                        ],
+                        vec![PlayerPropertyName::PlaylistPosition],
                     )
                     .await?;
                     log::debug!("Player properties: {:?}", props);
@@ -439,51 +1079,131 @@ pub async fn rpc_handler(
                     }
                     kodi_info_callback.playlist_position(playlist_position);
 
-                    if first_play {
-                        first_play = false;
-                        match &args.start_seconds {
-                            None => (),
-                            Some(start_seconds) => {
-                                use std::convert::TryFrom;
-                                kodi_rpc::player_seek(
-                                    &mut jsonrpc_session,
-                                    player_id,
-                                    Seek::RelativeSeconds {
-                                        seconds: i32::try_from(start_seconds.clone()).map_err(
-                                            |_| {
-                                                error::Error::MsgError(format!(
-                                                    "Cannot convert {} to signed 32-bit integer",
-                                                    start_seconds
-                                                ))
-                                            },
-                                        )?,
-                                    },
-                                )
+                    let want_item = capabilities.has_method("Player.GetItem")
+                        && (!args.starts.is_empty()
+                            || args.end.is_some()
+                            || keep_history
+                            || (!remote && args.notifications.item.is_some()));
+                    let item = if want_item {
+                        let item = kodi_rpc::player_get_item(
+                            &mut jsonrpc_session,
+                            player_id,
+                            vec![ListFieldsAll::File],
+                        )
+                        .await?
+                        .item;
+                        log::debug!("Player item: {:?}", item);
+                        Some(item)
+                    } else {
+                        None
+                    };
+                    // the index of the item in urls, and so its URL as the server made it
+                    let item_index = item
+                        .as_ref()
+                        .and_then(|item| item.file.as_ref())
+                        .and_then(|file| find_url(&urls, file));
+                    let item_url = item_index.map(|index| &urls[index]);
+                    history_index = item_index;
+
+                    if !remote {
+                        if let Some(item) = &item {
+                            let position = if use_playlist {
+                                props.playlist_position - playlist_offset + 1
+                            } else {
+                                1
+                            };
+                            notify(
+                                &mut jsonrpc_session,
+                                &args.notifications,
+                                &args.notifications.item,
+                                GUINotificationImage::Info,
+                                &[
+                                    ("title", item.label.clone()),
+                                    ("position", position.to_string()),
+                                    ("count", urls.len().to_string()),
+                                ],
+                            )
+                            .await;
+                        }
+                    }
+
+                    // the seek is done every time the item starts, e.g. when repeating
+                    match item_url.and_then(|url| args.starts.get(url)) {
+                        None => (),
+                        Some(start) => {
+                            log::info!("Starting from {:?}", start);
+                            kodi_rpc::player_seek(&mut jsonrpc_session, player_id, start.to_seek())
                                 .await?;
-                            }
                         }
                     }
 
+                    // only the first file given is bounded, wherever shuffling puts it
+                    boundary = match args.end {
+                        Some(_) if item_index == Some(0) => Some(tokio::time::Instant::now()),
+                        _ => None,
+                    };
+
                     state = State::WaitingLast;
                 }
-                Event::Notification(Notification::PlayerOnStop(_stop)) => {
+                Event::Notification(Notification::PlayerOnStop(stop)) => {
+                    if media_type == MediaType::Picture {
+                        // the slideshow has no streams to check, but its player goes away
+                        let players = kodi_rpc::get_active_players(&mut jsonrpc_session).await?;
+                        if players.iter().any(|player| player.playerid == player_id) {
+                            // probably the background audio stopping; the slideshow goes on
+                            continue;
+                        }
+                    }
                     let end = {
+                        let mut properties = vec![
+                            PlayerPropertyName::PlaylistPosition,
+                            PlayerPropertyName::Repeat,
+                        ];
+                        // without the stream any stop counts as the end
+                        properties.extend(media_type.stream_property().filter(|property| {
+                            serde_json::to_value(property)
+                                .ok()
+                                .and_then(|name| name.as_str().map(String::from))
+                                .map_or(true, |name| capabilities.has_player_property(&name))
+                        }));
                         let props = kodi_rpc::player_get_properties(
                             &mut jsonrpc_session,
                             player_id,
-                            vec![
-                                PlayerPropertyName::CurrentVideoStream,
-                                PlayerPropertyName::PlaylistPosition,
-                            ],
+                            properties,
                         )
                         .await?;
-                        match &props.current_video_stream {
-                            Some(PlayerVideoStream { codec, .. }) if codec.is_empty() => true,
-                            None => true,
-                            Some(_) => false,
-                        }
+                        // when repeating, an item ending on its own is followed by the next
+                        // (or the same) one starting, so it's never the end of the playlist.
+                        // if that doesn't happen, WaitingTimeout takes care of it.
+                        let looping = stop.data.end && props.repeat != PlayerRepeat::Off;
+                        let stream_ended = match media_type {
+                            MediaType::Video => match &props.current_video_stream {
+                                Some(PlayerVideoStream { codec, .. }) => codec.is_empty(),
+                                None => true,
+                            },
+                            MediaType::Audio => match &props.current_audio_stream {
+                                Some(PlayerAudioStream { codec, .. }) => codec.is_empty(),
+                                None => true,
+                            },
+                            // checked above
+                            MediaType::Picture => true,
+                        };
+                        !looping && stream_ended
                     };
-                    if end {
+                    if let (Some(history), Some(index)) =
+                        (history.lock().unwrap().as_mut(), history_index)
+                    {
+                        if stop.data.end {
+                            history.finished(index);
+                        }
+                    }
+                    // until the next item starts, whatever plays isn't ours
+                    history_index = None;
+                    if end && watching {
+                        log::info!("End of the playlist, waiting for more files");
+                        idle = true;
+                        state = State::WaitingStart;
+                    } else if end {
                         log::debug!("End of playback, trying to stop..");
                         break; // exit the loop
                     } else {
@@ -493,13 +1213,74 @@ pub async fn rpc_handler(
                         state = State::WaitingTimeout(deadline);
                     }
                 }
-                Event::Notification(_) => (), // ignore
+                Event::Notification(_) | Event::Unknown => (), // ignore
+                Event::Deadline if watching => {
+                    log::info!("Playback has stopped, waiting for more files");
+                    idle = true;
+                    state = State::WaitingStart;
+                }
                 Event::Deadline => {
                     // so it appears we have finished playing; do the finishing steps
                     break; // exit the loop
                 }
+                Event::Boundary => {
+                    let props = kodi_rpc::player_get_properties(
+                        &mut jsonrpc_session,
+                        player_id,
+                        vec![
+                            PlayerPropertyName::Time,
+                            PlayerPropertyName::TotalTime,
+                            PlayerPropertyName::Speed,
+                            PlayerPropertyName::PlaylistPosition,
+                        ],
+                    )
+                    .await?;
+                    if let (Some(history), Some(index)) =
+                        (history.lock().unwrap().as_mut(), history_index)
+                    {
+                        history.progress(index, props.time.clone(), props.total_time.clone());
+                    }
+                    let ms = props.time.map(|time| time.as_milliseconds()).unwrap_or(0);
+                    let total_ms = props
+                        .total_time
+                        .map(|time| time.as_milliseconds())
+                        .unwrap_or(0);
+                    // a percentage can't be resolved before the length is known
+                    let end_ms = match args.end {
+                        Some(Position::Percentage(_)) if total_ms == 0 => None,
+                        end => end.map(|end| end.as_milliseconds(total_ms)),
+                    };
+                    match end_ms {
+                        Some(end_ms) if ms >= end_ms => {
+                            boundary = None;
+                            if use_playlist && props.playlist_position < playlist_end {
+                                log::info!("Reached the end at {}ms, advancing", ms);
+                                kodi_rpc::player_goto(&mut jsonrpc_session, player_id, GoTo::Next)
+                                    .await?;
+                            } else {
+                                log::info!("Reached the end at {}ms, stopping", ms);
+                                break; // exit the loop
+                            }
+                        }
+                        _ => {
+                            // check again when the end should have been reached, but not too
+                            // rarely in case of seeking; while paused just keep polling
+                            let remaining_ms = match (end_ms, props.speed) {
+                                (Some(end_ms), speed) if speed > 0 => (end_ms - ms) / speed as u32,
+                                _ => 1000,
+                            };
+                            let delay = std::time::Duration::from_millis(
+                                remaining_ms.clamp(100, 5000) as u64,
+                            );
+                            boundary = Some(tokio::time::Instant::now() + delay);
+                        }
+                    }
+                }
                 Event::SigInt | Event::Exit => {
                     log::info!("Ctrl-c or exit, trying to stop..");
+                    if let Some(history) = history.lock().unwrap().as_mut() {
+                        history.interrupted();
+                    }
 
                     exit.signal();
                     match stop_server_tx.send(()) {
@@ -511,23 +1292,113 @@ pub async fn rpc_handler(
                     }
                     break; // exit the loop
                 }
+                Event::Enqueue(url, file) => {
+                    log::info!("Adding {} to the playlist", url);
+                    kodi_rpc::playlist_add(
+                        &mut jsonrpc_session,
+                        playlist_id,
+                        vec![url.to_string()],
+                    )
+                    .await?;
+                    let position = playlist_offset + urls.len() as PlaylistPosition;
+                    urls.push(url);
+                    playlist_end = position;
+                    if let Some(history) = history.lock().unwrap().as_mut() {
+                        history.add(file);
+                    }
+                    if idle {
+                        let item = PlayerOpenParamsItem::PlaylistPos {
+                            playlist_id,
+                            position,
+                        };
+                        kodi_rpc::player_open(&mut jsonrpc_session, item, None).await?;
+                        idle = false;
+                    }
+                }
                 Event::Control(mut control_request) => {
                     let context = ControlContext {
                         jsonrpc_session,
                         player_id,
                         kodi_info_callback: Some(kodi_info_callback),
+                        properties: None,
                     };
-                    let context = control_request.request_wrapper(context).await;
+                    let mut context = control_request.request_wrapper(context).await;
+                    if let (Some(history), Some(index), Some(props)) = (
+                        history.lock().unwrap().as_mut(),
+                        history_index,
+                        context.properties.take(),
+                    ) {
+                        history.progress(index, props.time, props.total_time);
+                    }
                     jsonrpc_session = context.jsonrpc_session;
                     kodi_info_callback = context.kodi_info_callback.unwrap();
                 }
             }
         }
-        finish(&mut jsonrpc_session, player_id, playlist_id, use_playlist).await?;
+        if !remote {
+            notify(
+                &mut jsonrpc_session,
+                &args.notifications,
+                &args.notifications.end,
+                GUINotificationImage::Info,
+                &[("count", urls.len().to_string())],
+            )
+            .await;
+        }
+        finish(
+            &mut jsonrpc_session,
+            player_id,
+            playlist_id,
+            use_playlist,
+            args.on_end,
+            restore,
+            &settings,
+            background_player_id,
+        )
+        .await?;
+
+        match args.power {
+            None => (),
+            Some(config::Power::Suspend) => {
+                log::info!("Suspending Kodi");
+                kodi_rpc::system_suspend(&mut jsonrpc_session).await?;
+            }
+            Some(config::Power::Shutdown) => {
+                log::info!("Shutting down Kodi");
+                kodi_rpc::system_shutdown(&mut jsonrpc_session).await?;
+            }
+        }
 
         Ok(())
     })
     .await;
+    // the session went down with the error, so these need a connection of their own; the
+    // settings are only left over when the session failed before finishing
+    let notify_error = result.is_err() && notifications.error.is_some();
+    if notify_error || !settings_at_end.lock().unwrap().is_empty() {
+        match kodi_http {
+            Some((url, auth)) => match kodi_rpc::connect_http(&url, &auth).await {
+                Ok(mut jsonrpc_session) => {
+                    restore_settings(&mut jsonrpc_session, &settings_at_end).await;
+                    if let Err(err) = &result {
+                        notify(
+                            &mut jsonrpc_session,
+                            &notifications,
+                            &notifications.error,
+                            kodi_rpc_types::GUINotificationImage::Error,
+                            &[("error", err.to_string())],
+                        )
+                        .await;
+                    }
+                }
+                Err(err) => log::error!("Failed to connect to Kodi after the session: {}", err),
+            },
+            None => log::debug!("No connection to Kodi after the session"),
+        }
+    }
+    if let Some(history) = history_at_end.lock().unwrap().take() {
+        history.finish(&result);
+    }
     rpc_handler_done_tx
         .send(result)
         .expect("Failed to send rpc_handler_done");
@@ -1,6 +1,6 @@
 use http_auth_basic::Credentials;
 
-use crate::{error, kodi_rpc_types::*};
+use crate::{capabilities::Capabilities, error, kodi_rpc_types::*, recording::Recorder};
 
 use url::Url;
 
@@ -13,6 +13,9 @@ use hyper::http::{Request, StatusCode};
 use hyper::{client::conn::Builder, Body};
 use tokio::net::TcpStream;
 
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
 pub struct GetResult {
     pub bytes: actix_web::web::Bytes,
     pub local_addr: std::net::SocketAddr,
@@ -22,7 +25,7 @@ pub struct GetResult {
 #[derive(Debug, serde::Serialize)]
 pub struct NoParameters {}
 
-const NO_PARAMS: Option<NoParameters> = None;
+pub(crate) const NO_PARAMS: Option<NoParameters> = None;
 
 // Used for discarding results of a request
 #[derive(Debug)]
@@ -147,16 +150,143 @@ pub async fn http_jsonrpc_get_setting(
     }
 }
 
+// How requests get to Kodi: the WebSocket, or HTTP POSTs to the web server when the
+// WebSocket port isn't reachable, in which case notifications are made up by polling
+enum Connection {
+    Ws(WsClient),
+    Http { client: HttpClient, url: Url },
+}
+
 pub struct WsJsonRPCSession {
-    client: WsClient,
+    connection: Connection,
+    capabilities: Capabilities,
+    recorder: Option<Arc<Recorder>>,
+}
+
+impl WsJsonRPCSession {
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    // records the calls from now on, and the notifications of subscriptions made after this
+    pub fn record(&mut self, recorder: Recorder) {
+        self.recorder = Some(Arc::new(recorder));
+    }
+
+    // what doesn't work with this Kodi, or over this connection
+    pub fn limitations(&self) -> Vec<String> {
+        let mut limitations = self.capabilities.limitations();
+        if let Connection::Http { .. } = self.connection {
+            limitations.push(format!(
+                "Kodi's WebSocket is not reachable, so its state is polled over HTTP every {}ms and koko reacts slower",
+                POLL_INTERVAL.as_millis()
+            ));
+        }
+        limitations
+    }
+
+    async fn raw_request(
+        &self,
+        name: &str,
+        params: Option<Params>,
+    ) -> Result<Output, error::Error> {
+        let recorded_params = match &self.recorder {
+            Some(_) => params.clone(),
+            None => None,
+        };
+        let output = match &self.connection {
+            Connection::Ws(client) => client.request(name, params).await?,
+            Connection::Http { client, .. } => client.request(name, params).await?,
+        };
+        if let Some(recorder) = &self.recorder {
+            recorder.call(name, recorded_params.as_ref(), &output);
+        }
+        Ok(output)
+    }
+}
+
+// a firewall may drop the packets instead of refusing the connection
+const WS_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+async fn ping(session: WsJsonRPCSession) -> Result<WsJsonRPCSession, error::Error> {
+    match session.raw_request("JSONRPC.Ping", None).await? {
+        Output::Success(_) => Ok(session),
+        Output::Failure(value) => Err(error::Error::JsonrpcError(value)),
+    }
 }
 
 pub async fn connect(url: &Url) -> Result<WsJsonRPCSession, error::Error> {
     let client = WsClient::new(url.as_str()).await?;
-    let response = client.request("JSONRPC.Ping", None).await?;
-    match response {
-        Output::Success(_) => Ok(WsJsonRPCSession { client }),
-        Output::Failure(value) => Err(error::Error::JsonrpcError(value)),
+    ping(WsJsonRPCSession {
+        connection: Connection::Ws(client),
+        capabilities: Capabilities::default(),
+        recorder: None,
+    })
+    .await
+}
+
+// url is that of the web server, as for jsonrpc_get
+pub async fn connect_http(
+    url: &Url,
+    auth: &Option<(String, String)>,
+) -> Result<WsJsonRPCSession, error::Error> {
+    let mut url = url.clone();
+    if let Some((user, pass)) = auth {
+        let _ = url.set_username(user);
+        let _ = url.set_password(Some(pass));
+    }
+    let client = HttpClient::new(url.as_str())?;
+    ping(WsJsonRPCSession {
+        connection: Connection::Http { client, url },
+        capabilities: Capabilities::default(),
+        recorder: None,
+    })
+    .await
+}
+
+// Over the WebSocket if possible, otherwise over HTTP
+pub async fn connect_any(
+    wsurl: &Url,
+    url: &Url,
+    auth: &Option<(String, String)>,
+) -> Result<WsJsonRPCSession, error::Error> {
+    let error = match tokio::time::timeout(WS_CONNECT_TIMEOUT, connect(wsurl)).await {
+        Ok(Ok(session)) => return Ok(session),
+        Ok(Err(err)) => err.to_string(),
+        Err(_) => format!("no response in {} seconds", WS_CONNECT_TIMEOUT.as_secs()),
+    };
+    log::warn!(
+        "Cannot connect to {} ({}), falling back to HTTP at {}",
+        wsurl,
+        error,
+        url
+    );
+    connect_http(url, auth).await
+}
+
+// Finds out what this Kodi supports. Failing that, everything is assumed to work, as
+// before.
+pub async fn detect_capabilities(session: &mut WsJsonRPCSession) {
+    let version = match jsonrpc_version(session).await {
+        Ok(returns) => Some(returns.version),
+        Err(err) => {
+            log::warn!("Cannot get the JSON-RPC API version: {}", err);
+            None
+        }
+    };
+    match jsonrpc_introspect(session).await {
+        Ok(introspect) => session.capabilities = Capabilities::new(version, &introspect),
+        Err(err) => {
+            log::warn!("Cannot get the JSON-RPC API schema: {}", err);
+            session.capabilities.version = version;
+        }
+    }
+    match version {
+        Some(version) => log::info!("Kodi JSON-RPC API v{}", version),
+        None => log::info!("Kodi JSON-RPC API version unknown"),
+    }
+    for limitation in session.limitations() {
+        log::warn!("{}", limitation);
     }
 }
 
@@ -172,6 +302,15 @@ pub async fn player_seek(
     player_id: PlayerId,
     value: Seek,
 ) -> Result<PlayerSeekReturns, error::Error> {
+    if session.capabilities.legacy_seek() {
+        let value = LegacySeek::from(value);
+        return request(
+            session,
+            "Player.Seek",
+            Some(PlayerLegacySeekParams { player_id, value }),
+        )
+        .await;
+    }
     request(
         session,
         "Player.Seek",
@@ -192,37 +331,225 @@ pub async fn get_active_players(
     request(session, "Player.GetActivePlayers", NO_PARAMS).await
 }
 
+enum Source {
+    Ws(WsSubscription<WsNotification, ()>),
+    Polled(Box<Poller>),
+}
+
 pub struct Subscription {
-    ws_subscription: WsSubscription<WsNotification, ()>,
+    source: Source,
+    recorder: Option<Arc<Recorder>>,
+}
+
+// A notification as Kodi sent it, along with the parsed one if it's one we know
+#[derive(Debug, Clone)]
+pub struct RawNotification {
+    pub value: serde_json::Value,
+    pub notification: Option<Notification>,
 }
 
 impl Subscription {
     pub async fn next(&mut self) -> Option<Notification> {
         loop {
-            match self.ws_subscription.next().await.map(|notification| {
+            if let Some(notification) = self.next_raw().await?.notification {
+                return Some(notification);
+            }
+        }
+    }
+
+    // like next, but doesn't skip the notifications that fail to parse
+    pub async fn next_raw(&mut self) -> Option<RawNotification> {
+        let value = match &mut self.source {
+            Source::Ws(ws_subscription) => {
+                let notification = ws_subscription.next().await?;
                 log::debug!("notification: {:?}", notification);
-                match serde_json::from_value(
-                    serde_json::to_value(&notification).expect("Failed to serialize notification"),
-                ) {
-                    Ok(x) => Some(x),
-                    Err(_) => None,
-                }
-            }) {
-                Some(Some(x)) => return Some(x),
-                None => return None,
-                Some(None) => (), // loop
+                serde_json::to_value(&notification).expect("Failed to serialize notification")
             }
+            Source::Polled(poller) => poller.next().await?,
+        };
+        if let Some(recorder) = &self.recorder {
+            recorder.notification(&value);
         }
+        Some(RawNotification {
+            notification: serde_json::from_value(value.clone()).ok(),
+            value,
+        })
     }
 }
 
 pub async fn subscribe(session: &mut WsJsonRPCSession) -> Result<Subscription, error::Error> {
-    let ws_subscription = session
-        .client
-        .subscribe_all()
-        .await
-        .map_err(|err| error::Error::JsonrpcWsClientError(err))?;
-    Ok(Subscription { ws_subscription })
+    let source = match &session.connection {
+        Connection::Ws(client) => {
+            let ws_subscription = client
+                .subscribe_all()
+                .await
+                .map_err(|err| error::Error::JsonrpcWsClientError(err))?;
+            Source::Ws(ws_subscription)
+        }
+        // polling has its own connection so that it doesn't need to share the session
+        Connection::Http { url, .. } => {
+            let session = WsJsonRPCSession {
+                connection: Connection::Http {
+                    client: HttpClient::new(url.as_str())?,
+                    url: url.clone(),
+                },
+                capabilities: session.capabilities.clone(),
+                // the polls aren't part of the session; the notifications made of them are
+                recorder: None,
+            };
+            Source::Polled(Box::new(Poller::new(session)))
+        }
+    };
+    Ok(Subscription {
+        source,
+        recorder: session.recorder.clone(),
+    })
+}
+
+const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+
+// giving up on Kodi after this many failed polls in a row, like a closed WebSocket
+const POLL_MAX_FAILURES: u32 = 10;
+
+// what polling last saw of a player
+#[derive(Debug, Clone)]
+struct PolledPlayer {
+    speed: i32,
+    playlist_position: PlaylistPosition,
+    ms: u32,
+    total_ms: u32,
+}
+
+impl PolledPlayer {
+    // whether it stopped at the end rather than being stopped; polling only sees the time
+    // every POLL_INTERVAL, so this can't be exact
+    fn near_end(&self) -> bool {
+        let margin = 2 * POLL_INTERVAL.as_millis() as u32 + 1000;
+        self.total_ms > 0 && self.ms + margin >= self.total_ms
+    }
+}
+
+// Makes up the player notifications rpc_handler needs from changes in the active players
+pub struct Poller {
+    session: WsJsonRPCSession,
+    players: HashMap<PlayerId, PolledPlayer>,
+    pending: VecDeque<serde_json::Value>,
+    failures: u32,
+}
+
+fn make_notification(method: &str, data: serde_json::Value) -> serde_json::Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": { "sender": "xbmc", "data": data },
+    })
+}
+
+impl Poller {
+    fn new(session: WsJsonRPCSession) -> Poller {
+        Poller {
+            session,
+            players: HashMap::new(),
+            pending: VecDeque::new(),
+            failures: 0,
+        }
+    }
+
+    async fn next(&mut self) -> Option<serde_json::Value> {
+        loop {
+            if let Some(notification) = self.pending.pop_front() {
+                return Some(notification);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+            match self.poll().await {
+                Ok(()) => self.failures = 0,
+                Err(err) => {
+                    log::error!("Failed to poll Kodi: {}", err);
+                    self.failures += 1;
+                    if self.failures >= POLL_MAX_FAILURES {
+                        return None;
+                    }
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, method: &str, player_id: PlayerId, speed: i32) {
+        let data = serde_json::json!({
+            // the item isn't used, so it's not worth fetching
+            "item": { "type": "unknown" },
+            "player": { "playerid": player_id, "speed": speed },
+        });
+        self.pending.push_back(make_notification(method, data));
+    }
+
+    fn push_stop(&mut self, end: bool) {
+        let data = serde_json::json!({ "item": { "type": "unknown" }, "end": end });
+        self.pending
+            .push_back(make_notification("Player.OnStop", data));
+    }
+
+    // only AVStart; rpc_handler would take OnPlay as a start as well when it's the picture
+    // player
+    fn push_start(&mut self, player_id: PlayerId, speed: i32) {
+        self.push("Player.OnAVStart", player_id, speed);
+    }
+
+    async fn poll(&mut self) -> Result<(), error::Error> {
+        let mut players = HashMap::new();
+        for player in get_active_players(&mut self.session).await? {
+            let props = player_get_properties(
+                &mut self.session,
+                player.playerid,
+                vec![
+                    PlayerPropertyName::Speed,
+                    PlayerPropertyName::PlaylistPosition,
+                    PlayerPropertyName::Time,
+                    PlayerPropertyName::TotalTime,
+                ],
+            )
+            .await?;
+            let current = PolledPlayer {
+                speed: props.speed,
+                playlist_position: props.playlist_position,
+                ms: props.time.map(|time| time.as_milliseconds()).unwrap_or(0),
+                total_ms: props
+                    .total_time
+                    .map(|time| time.as_milliseconds())
+                    .unwrap_or(0),
+            };
+            match self.players.get(&player.playerid).cloned() {
+                None => self.push_start(player.playerid, current.speed),
+                // the next item in the playlist, or the same one again when repeating
+                Some(previous)
+                    if previous.playlist_position != current.playlist_position
+                        || (previous.near_end() && current.ms < previous.ms) =>
+                {
+                    self.push_stop(true);
+                    self.push_start(player.playerid, current.speed);
+                }
+                Some(previous) if previous.speed != 0 && current.speed == 0 => {
+                    self.push("Player.OnPause", player.playerid, current.speed)
+                }
+                Some(previous) if previous.speed == 0 && current.speed != 0 => {
+                    self.push("Player.OnResume", player.playerid, current.speed)
+                }
+                Some(_) => (),
+            }
+            players.insert(player.playerid, current);
+        }
+        let stopped: Vec<bool> = self
+            .players
+            .iter()
+            .filter(|(player_id, _)| !players.contains_key(player_id))
+            .map(|(_, previous)| previous.near_end())
+            .collect();
+        for end in stopped {
+            self.push_stop(end);
+        }
+        self.players = players;
+        Ok(())
+    }
 }
 
 fn value_to_params(value: serde_json::Value) -> Option<Params> {
@@ -233,7 +560,36 @@ fn value_to_params(value: serde_json::Value) -> Option<Params> {
     }
 }
 
-async fn request<Request: serde::Serialize, Response: serde::de::DeserializeOwned>(
+// Any method with any parameters, for when there's no wrapper; params must be an object or
+// an array, as JSON-RPC requires
+pub async fn call(
+    session: &mut WsJsonRPCSession,
+    method: &str,
+    params: Option<serde_json::Value>,
+) -> Result<serde_json::Value, error::Error> {
+    match params {
+        None => request(session, method, NO_PARAMS).await,
+        Some(params) if params.is_object() || params.is_array() => {
+            request(session, method, Some(params)).await
+        }
+        Some(params) => Err(error::Error::MsgError(format!(
+            "Parameters must be an object or an array, not {}",
+            params
+        ))),
+    }
+}
+
+// Kodi's own error object rather than just "Failed to communicate over JSONRPC"
+pub fn describe_error(err: &error::Error) -> String {
+    match err {
+        error::Error::JsonrpcError(failure) => {
+            serde_json::to_string_pretty(&failure.error).unwrap_or_else(|_| err.to_string())
+        }
+        err => err.to_string(),
+    }
+}
+
+pub(crate) async fn request<Request: serde::Serialize, Response: serde::de::DeserializeOwned>(
     session: &mut WsJsonRPCSession,
     name: &str,
     request: Option<Request>,
@@ -242,8 +598,7 @@ where
     Response: std::fmt::Debug,
 {
     let response = session
-        .client
-        .request(
+        .raw_request(
             name,
             request.map(|x| {
                 let value = serde_json::to_value(x).expect("Cannot serialize request");
@@ -270,8 +625,14 @@ where
 pub async fn player_open(
     session: &mut WsJsonRPCSession,
     item: PlayerOpenParamsItem,
+    options: Option<PlayerOpenOptions>,
 ) -> Result<Discard, error::Error> {
-    request(session, "Player.Open", Some(PlayerOpenParams { item })).await
+    request(
+        session,
+        "Player.Open",
+        Some(PlayerOpenParams { item, options }),
+    )
+    .await
 }
 
 pub async fn player_play_pause(
@@ -316,20 +677,62 @@ pub async fn player_get_properties(
     .await
 }
 
+pub async fn player_get_item(
+    session: &mut WsJsonRPCSession,
+    player_id: PlayerId,
+    properties: Vec<ListFieldsAll>,
+) -> Result<PlayerGetItemReturns, error::Error> {
+    request(
+        session,
+        "Player.GetItem",
+        Some(PlayerGetItemParams {
+            player_id,
+            properties,
+        }),
+    )
+    .await
+}
+
 pub async fn playlist_add(
     session: &mut WsJsonRPCSession,
     playlist_id: PlaylistId,
     files: Vec<String>,
+) -> Result<Discard, error::Error> {
+    playlist_add_items(
+        session,
+        playlist_id,
+        files
+            .into_iter()
+            .map(|file| PlaylistItem::File { file })
+            .collect(),
+    )
+    .await
+}
+
+pub async fn playlist_add_items(
+    session: &mut WsJsonRPCSession,
+    playlist_id: PlaylistId,
+    items: Vec<PlaylistItem>,
 ) -> Result<Discard, error::Error> {
     request(
         session,
         "Playlist.Add",
-        Some(PlaylistAddParams {
+        Some(PlaylistAddParams { playlist_id, items }),
+    )
+    .await
+}
+
+pub async fn playlist_get_items(
+    session: &mut WsJsonRPCSession,
+    playlist_id: PlaylistId,
+    properties: Vec<ListFieldsAll>,
+) -> Result<PlaylistGetItemsReturns, error::Error> {
+    request(
+        session,
+        "Playlist.GetItems",
+        Some(PlaylistGetItemsParams {
             playlist_id,
-            items: files
-                .into_iter()
-                .map(|file| PlaylistItem::File { file })
-                .collect(),
+            properties,
         }),
     )
     .await
@@ -360,10 +763,85 @@ pub async fn gui_activate_window(
     .await
 }
 
+pub async fn system_suspend(session: &mut WsJsonRPCSession) -> Result<Discard, error::Error> {
+    request(session, "System.Suspend", NO_PARAMS).await
+}
+
+pub async fn system_shutdown(session: &mut WsJsonRPCSession) -> Result<Discard, error::Error> {
+    request(session, "System.Shutdown", NO_PARAMS).await
+}
+
+pub async fn gui_show_notification(
+    session: &mut WsJsonRPCSession,
+    params: GUIShowNotificationParams,
+) -> Result<Discard, error::Error> {
+    request(session, "GUI.ShowNotification", Some(params)).await
+}
+
+pub async fn gui_get_properties(
+    session: &mut WsJsonRPCSession,
+    properties: Vec<GUIPropertyName>,
+) -> Result<GUIPropertyValue, error::Error> {
+    request(
+        session,
+        "GUI.GetProperties",
+        Some(GUIGetPropertiesParams { properties }),
+    )
+    .await
+}
+
+pub async fn files_get_file_details(
+    session: &mut WsJsonRPCSession,
+    file: &str,
+) -> Result<serde_json::Value, error::Error> {
+    request(
+        session,
+        "Files.GetFileDetails",
+        Some(FilesGetFileDetailsParams {
+            file: String::from(file),
+            media: String::from("files"),
+        }),
+    )
+    .await
+}
+
+pub async fn input_button(
+    session: &mut WsJsonRPCSession,
+    button: InputButton,
+) -> Result<Discard, error::Error> {
+    request(session, button.method(), NO_PARAMS).await
+}
+
+pub async fn input_send_text(
+    session: &mut WsJsonRPCSession,
+    text: &str,
+    done: bool,
+) -> Result<Discard, error::Error> {
+    request(
+        session,
+        "Input.SendText",
+        Some(InputSendTextParams {
+            text: String::from(text),
+            done,
+        }),
+    )
+    .await
+}
+
+pub async fn jsonrpc_version(
+    session: &mut WsJsonRPCSession,
+) -> Result<JSONRPCVersionReturns, error::Error> {
+    request(session, "JSONRPC.Version", NO_PARAMS).await
+}
+
 pub async fn jsonrpc_introspect(
     session: &mut WsJsonRPCSession,
 ) -> Result<serde_json::Value, error::Error> {
-    let response = session.client.request("JSONRPC.Introspect", None).await?;
+    // over HTTP Kodi would leave out the notifications, which the poller makes up
+    let params = serde_json::json!({ "filterbytransport": false });
+    let response = session
+        .raw_request("JSONRPC.Introspect", value_to_params(params))
+        .await?;
     match response {
         Output::Success(response) => Ok(response.result),
         Output::Failure(value) => Err(error::Error::JsonrpcError(value)),
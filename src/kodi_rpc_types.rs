@@ -9,6 +9,10 @@ pub type PlaylistPosition = i32; // positive but default is -1
 #[serde(untagged)]
 pub enum PlaylistItem {
     File { file: String },
+    MovieId { movieid: u32 },
+    EpisodeId { episodeid: u32 },
+    MusicVideoId { musicvideoid: u32 },
+    SongId { songid: u32 },
 }
 
 #[derive(Debug, Serialize)]
@@ -26,10 +30,76 @@ pub struct PlaylistClearParams {
     pub playlist_id: PlaylistId,
 }
 
+// List.Fields.All, the parts we use
+#[derive(Debug, Serialize, Clone)]
+pub enum ListFieldsAll {
+    #[serde(rename = "file")]
+    File,
+    #[serde(rename = "title")]
+    Title,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PlaylistGetItemsParams {
     #[serde(rename = "playlistid")]
     pub playlist_id: PlaylistId,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<ListFieldsAll>,
+}
+
+// List.Item.All, the parts we use
+#[derive(Debug, Deserialize, Clone)]
+pub struct ListItemAll {
+    #[serde(default)]
+    pub id: Option<u32>,
+
+    #[serde(default, rename = "type")]
+    pub type_: String,
+
+    #[serde(default)]
+    pub file: Option<String>,
+
+    #[serde(default)]
+    pub label: String,
+}
+
+impl ListItemAll {
+    // library items are referred to by their id, everything else by the file
+    pub fn to_playlist_item(&self) -> Option<PlaylistItem> {
+        match (self.type_.as_str(), self.id, &self.file) {
+            ("movie", Some(movieid), _) => Some(PlaylistItem::MovieId { movieid }),
+            ("episode", Some(episodeid), _) => Some(PlaylistItem::EpisodeId { episodeid }),
+            ("musicvideo", Some(musicvideoid), _) => {
+                Some(PlaylistItem::MusicVideoId { musicvideoid })
+            }
+            ("song", Some(songid), _) => Some(PlaylistItem::SongId { songid }),
+            (_, _, Some(file)) => Some(PlaylistItem::File { file: file.clone() }),
+            (_, _, None) => None,
+        }
+    }
+}
+
+// Player.GetItem
+#[derive(Debug, Serialize)]
+pub struct PlayerGetItemParams {
+    #[serde(rename = "playerid")]
+    pub player_id: PlayerId,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub properties: Vec<ListFieldsAll>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PlayerGetItemReturns {
+    pub item: ListItemAll,
+}
+
+// Playlist.GetItems
+#[derive(Debug, Deserialize, Clone)]
+pub struct PlaylistGetItemsReturns {
+    #[serde(default)]
+    pub items: Vec<ListItemAll>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -197,9 +267,20 @@ pub enum PlayerOpenParamsItem {
     PlaylistItem(PlaylistItem),
 }
 
+// Player.Open options
+#[derive(Debug, Serialize)]
+pub struct PlayerOpenOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shuffled: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<PlayerRepeat>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct PlayerOpenParams {
     pub item: PlayerOpenParamsItem,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub options: Option<PlayerOpenOptions>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -273,6 +354,19 @@ pub struct PlayerVideoStream {
     pub name: String,
 }
 
+// Player.Audio.Stream
+#[derive(Debug, Deserialize, Clone)]
+pub struct PlayerAudioStream {
+    pub codec: String,
+    #[serde(default)]
+    pub bitrate: u32,
+    #[serde(default)]
+    pub channels: u32,
+    pub index: u32,
+    pub language: String,
+    pub name: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct GlobalTime {
     pub hours: u8,
@@ -281,6 +375,32 @@ pub struct GlobalTime {
     pub seconds: u8,
 }
 
+impl GlobalTime {
+    pub fn from_seconds(seconds: u32) -> GlobalTime {
+        GlobalTime {
+            hours: (seconds / 3600) as u8,
+            minutes: (seconds / 60 % 60) as u8,
+            seconds: (seconds % 60) as u8,
+            milliseconds: 0,
+        }
+    }
+
+    pub fn from_milliseconds(milliseconds: u32) -> GlobalTime {
+        GlobalTime {
+            milliseconds: (milliseconds % 1000) as i16,
+            ..GlobalTime::from_seconds(milliseconds / 1000)
+        }
+    }
+
+    pub fn as_milliseconds(&self) -> u32 {
+        (self.as_seconds() as i64 * 1000 + self.milliseconds as i64).max(0) as u32
+    }
+
+    pub fn as_seconds(&self) -> u32 {
+        self.hours as u32 * 3600 + self.minutes as u32 * 60 + self.seconds as u32
+    }
+}
+
 pub type PlayerPositionTime = GlobalTime;
 
 // Player.Property.Value
@@ -293,9 +413,6 @@ pub struct PlayerPropertyValue {
     //   },
     //   "type": "array"
     // },
-    // "currentaudiostream": {
-    //   "$ref": "Player.Audio.Stream"
-    // },
     // "currentsubtitle": {
     //   "$ref": "Player.Subtitle"
     // },
@@ -305,10 +422,6 @@ pub struct PlayerPropertyValue {
     //   },
     //   "type": "array"
     // },
-    // "repeat": {
-    //   "$ref": "Player.Repeat",
-    //   "default": "off"
-    // },
     #[serde(default, rename = "canchangespeed")]
     pub can_change_speed: bool,
     #[serde(default, rename = "canmove")]
@@ -323,6 +436,8 @@ pub struct PlayerPropertyValue {
     pub can_shuffle: bool,
     #[serde(default, rename = "canzoom")]
     pub can_zoom: bool,
+    #[serde(default, rename = "currentaudiostream")]
+    pub current_audio_stream: Option<PlayerAudioStream>,
     #[serde(default, rename = "currentvideostream")]
     pub current_video_stream: Option<PlayerVideoStream>,
     #[serde(default, rename = "live")]
@@ -335,6 +450,8 @@ pub struct PlayerPropertyValue {
     pub playlist_id: PlaylistId,
     #[serde(default = "default_playlist_position", rename = "position")]
     pub playlist_position: PlaylistPosition,
+    #[serde(default, rename = "repeat")]
+    pub repeat: PlayerRepeat,
     #[serde(default, rename = "shuffled")]
     pub shuffled: bool,
     #[serde(default, rename = "speed")]
@@ -360,7 +477,7 @@ fn default_playlist_position() -> PlaylistPosition {
 }
 
 // Global.Toggle
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum GlobalToggle {
     False,
     True,
@@ -393,6 +510,36 @@ pub struct PlayerPlayPauseParams {
     pub play: GlobalToggle,
 }
 
+// Player.Repeat
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum PlayerRepeat {
+    #[serde(rename = "off")]
+    Off,
+    #[serde(rename = "one")]
+    One,
+    #[serde(rename = "all")]
+    All,
+}
+
+impl Default for PlayerRepeat {
+    fn default() -> PlayerRepeat {
+        PlayerRepeat::Off
+    }
+}
+
+impl std::str::FromStr for PlayerRepeat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PlayerRepeat, String> {
+        match s {
+            "off" => Ok(PlayerRepeat::Off),
+            "one" => Ok(PlayerRepeat::One),
+            "all" => Ok(PlayerRepeat::All),
+            other => Err(format!("Invalid repeat mode: {}", other)),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub enum GoTo {
     #[serde(rename = "previous")]
@@ -408,7 +555,7 @@ pub struct PlayerGoToParams {
     pub to: GoTo,
 }
 
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
 pub enum Step {
     #[serde(rename = "smallforward")]
     SmallForward,
@@ -434,6 +581,27 @@ pub enum Seek {
     RelativeSeconds { seconds: i32 },
 }
 
+// the bare forms that Kodi before API v10 (Kodi 18) takes instead of Seek
+#[derive(Debug, Serialize, Clone)]
+#[serde(untagged)]
+pub enum LegacySeek {
+    Percentage(f64),
+    Time(PlayerPositionTime),
+    Step(Step),
+    Seconds { seconds: i32 },
+}
+
+impl From<Seek> for LegacySeek {
+    fn from(seek: Seek) -> LegacySeek {
+        match seek {
+            Seek::AbsolutePercentage { percentage } => LegacySeek::Percentage(percentage),
+            Seek::AbsoluteTime { time } => LegacySeek::Time(time),
+            Seek::RelativeStep { step } => LegacySeek::Step(step),
+            Seek::RelativeSeconds { seconds } => LegacySeek::Seconds { seconds },
+        }
+    }
+}
+
 // Player.Seek
 #[derive(Debug, Serialize, Clone)]
 pub struct PlayerSeekParams {
@@ -443,6 +611,15 @@ pub struct PlayerSeekParams {
     pub value: Seek,
 }
 
+// Player.Seek, before API v10
+#[derive(Debug, Serialize, Clone)]
+pub struct PlayerLegacySeekParams {
+    #[serde(rename = "playerid")]
+    pub player_id: PlayerId,
+    #[serde(rename = "value")]
+    pub value: LegacySeek,
+}
+
 // Player.Seek
 #[derive(Debug, Deserialize, Clone)]
 pub struct PlayerSeekReturns {
@@ -470,6 +647,26 @@ pub struct SettingsFilter {
     pub section: String,
 }
 
+// JSONRPC.Version
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub struct JSONRPCVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl std::fmt::Display for JSONRPCVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+// JSONRPC.Version
+#[derive(Debug, Deserialize, Clone)]
+pub struct JSONRPCVersionReturns {
+    pub version: JSONRPCVersion,
+}
+
 // Settings.GetSettings
 #[derive(Debug, Serialize, Clone)]
 pub struct SettingsGetSettingsParams {
@@ -479,6 +676,86 @@ pub struct SettingsGetSettingsParams {
     pub filter: Option<SettingsFilter>,
 }
 
+// Files.GetFileDetails
+#[derive(Debug, Serialize, Clone)]
+pub struct FilesGetFileDetailsParams {
+    pub file: String,
+    pub media: String,
+}
+
+// Input.Up, Input.Select etc.; these take no parameters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputButton {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Back,
+    ContextMenu,
+    Info,
+    ShowOSD,
+}
+
+impl InputButton {
+    pub fn method(&self) -> &'static str {
+        match self {
+            InputButton::Up => "Input.Up",
+            InputButton::Down => "Input.Down",
+            InputButton::Left => "Input.Left",
+            InputButton::Right => "Input.Right",
+            InputButton::Select => "Input.Select",
+            InputButton::Back => "Input.Back",
+            InputButton::ContextMenu => "Input.ContextMenu",
+            InputButton::Info => "Input.Info",
+            InputButton::ShowOSD => "Input.ShowOSD",
+        }
+    }
+}
+
+// Input.SendText
+#[derive(Debug, Serialize)]
+pub struct InputSendTextParams {
+    pub text: String,
+    // closes the keyboard dialog
+    pub done: bool,
+}
+
+// GUI.ShowNotification; Kodi also accepts an image URL but we only use the built-in icons
+#[derive(Debug, Serialize, Clone, Copy, PartialEq)]
+pub enum GUINotificationImage {
+    #[serde(rename = "info")]
+    Info,
+    #[serde(rename = "warning")]
+    Warning,
+    #[serde(rename = "error")]
+    Error,
+}
+
+impl std::str::FromStr for GUINotificationImage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GUINotificationImage, String> {
+        match s {
+            "info" => Ok(GUINotificationImage::Info),
+            "warning" => Ok(GUINotificationImage::Warning),
+            "error" => Ok(GUINotificationImage::Error),
+            other => Err(format!("Invalid notification image: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct GUIShowNotificationParams {
+    pub title: String,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image: Option<GUINotificationImage>,
+    // milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub displaytime: Option<u32>,
+}
+
 // GUI.ActivateWindow
 #[derive(Debug, Serialize)]
 pub struct GUIActivateWindowParams {
@@ -486,7 +763,42 @@ pub struct GUIActivateWindowParams {
     pub parameters: Vec<String>, // must have at least one value
 }
 
+// GUI.GetProperties
+#[derive(Debug, Serialize, Clone)]
+pub enum GUIPropertyName {
+    #[serde(rename = "currentwindow")]
+    CurrentWindow,
+    #[serde(rename = "currentcontrol")]
+    CurrentControl,
+    #[serde(rename = "skin")]
+    Skin,
+    #[serde(rename = "fullscreen")]
+    Fullscreen,
+    #[serde(rename = "stereoscopicmode")]
+    StereoscopicMode,
+}
+
 #[derive(Debug, Serialize)]
+pub struct GUIGetPropertiesParams {
+    pub properties: Vec<GUIPropertyName>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GUIWindowInfo {
+    pub id: u32,
+    pub label: String,
+}
+
+// GUI.Property.Value
+#[derive(Debug, Deserialize, Clone)]
+pub struct GUIPropertyValue {
+    #[serde(default, rename = "currentwindow")]
+    pub current_window: Option<GUIWindowInfo>,
+    #[serde(default, rename = "fullscreen")]
+    pub fullscreen: bool,
+}
+
+#[derive(Debug, Serialize, Clone)]
 pub enum GUIWindow {
     #[serde(rename = "accesspoints")]
     Accespoints,
@@ -743,3 +1055,36 @@ pub enum GUIWindow {
     #[serde(rename = "yesnodialog")]
     YesNoDialog,
 }
+
+impl GUIWindow {
+    // GUI.GetProperties reports the numeric window id, while GUI.ActivateWindow wants a name.
+    // Only the windows that make sense to return to are mapped.
+    pub fn from_id(id: u32) -> Option<GUIWindow> {
+        match id {
+            10000 => Some(GUIWindow::Home),
+            10001 => Some(GUIWindow::Programs),
+            10002 => Some(GUIWindow::Pictures),
+            10003 => Some(GUIWindow::Filemanager),
+            10004 => Some(GUIWindow::Settings),
+            10007 => Some(GUIWindow::SystemInfo),
+            10025 => Some(GUIWindow::Videos),
+            10028 => Some(GUIWindow::VideoPlaylist),
+            10040 => Some(GUIWindow::AddonBrowser),
+            10500 => Some(GUIWindow::MusicPlaylist),
+            10502 => Some(GUIWindow::Music),
+            10700 => Some(GUIWindow::TvChannels),
+            10701 => Some(GUIWindow::TvRecordings),
+            10702 => Some(GUIWindow::TvGuide),
+            10703 => Some(GUIWindow::TvTimers),
+            10704 => Some(GUIWindow::TvSearch),
+            10705 => Some(GUIWindow::RadioChannels),
+            10706 => Some(GUIWindow::RadioRecordings),
+            10707 => Some(GUIWindow::RadioGuide),
+            10708 => Some(GUIWindow::RadioTimers),
+            10709 => Some(GUIWindow::RadioSearch),
+            10821 => Some(GUIWindow::Games),
+            12600 => Some(GUIWindow::Weather),
+            _ => None,
+        }
+    }
+}
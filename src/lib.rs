@@ -1,11 +1,32 @@
+pub mod capabilities;
 pub mod config;
+pub mod doctor;
 pub mod error;
 pub mod exit;
+#[cfg(feature = "mock")]
+pub mod fake_kodi;
+pub mod history;
+pub mod jsonl;
+pub mod keymap;
+pub mod kodi_api;
 pub mod kodi_control;
 pub mod kodi_rpc;
 pub mod kodi_rpc_types;
+pub mod logging;
+pub mod media;
+#[cfg(feature = "mock")]
+pub mod mock_kodi;
+pub mod position;
+pub mod recording;
 pub mod server;
 pub mod ui;
+pub mod ui_chapters;
+pub mod ui_passthrough;
 pub mod ui_seek;
+pub mod ui_seekbar;
 pub mod util;
 pub mod version;
+pub mod watch;
+pub mod wol;
+#[cfg(feature = "mock")]
+pub mod ws_server;
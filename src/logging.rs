@@ -0,0 +1,176 @@
+// Sets up log4rs from the command line and the [logging] section: a size-rotated file,
+// as text or as JSON lines, with per-module levels on top of the overall one.
+use crate::config::LogFormat;
+
+use directories::ProjectDirs;
+use log::LevelFilter;
+use log4rs::append::rolling_file::policy::compound::{
+    roll::delete::DeleteRoller, roll::fixed_window::FixedWindowRoller, roll::Roll,
+    trigger::size::SizeTrigger, CompoundPolicy,
+};
+use log4rs::append::rolling_file::RollingFileAppender;
+use log4rs::config::{Appender, Config, Logger, Root};
+use log4rs::encode::{json::JsonEncoder, pattern::PatternEncoder, Encode};
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+
+    #[error("Invalid log level: {}", .0)]
+    LevelError(String),
+
+    #[error("Cannot set up log rotation: {}", .0)]
+    RotationError(String),
+
+    #[error(transparent)]
+    ConfigError(#[from] log4rs::config::runtime::ConfigErrors),
+
+    #[error(transparent)]
+    SetLoggerError(#[from] log::SetLoggerError),
+
+    #[error("Cannot determine where to write the log, please give one with --log-file")]
+    NoLogFileError,
+}
+
+// the target of the UI's periodic polling, which logs every half a second
+pub const POLL_TARGET: &str = "kodi_kontrol::ui::poll";
+
+// module -> level, overriding the overall level for the module and its submodules
+pub type ModuleLevels = Vec<(String, LevelFilter)>;
+
+pub const DEFAULT_MAX_SIZE_KB: u64 = 10 * 1024;
+pub const DEFAULT_KEEP: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub level: LevelFilter,
+    pub modules: ModuleLevels,
+    pub file: PathBuf,
+    pub format: LogFormat,
+    // the file is rotated once it is larger than this
+    pub max_size_kb: u64,
+    // how many rotated files are kept, as file.1, file.2, ..
+    pub keep: u32,
+}
+
+pub fn parse_level(level: &str) -> Result<LevelFilter, Error> {
+    level
+        .parse()
+        .map_err(|_| Error::LevelError(String::from(level)))
+}
+
+// "debug,kodi_kontrol::ui::poll=off" -> the overall level and the module levels
+pub fn parse_levels(spec: &str) -> Result<(Option<LevelFilter>, ModuleLevels), Error> {
+    let mut level = None;
+    let mut modules = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|x| !x.is_empty()) {
+        match part.split_once('=') {
+            Some((module, module_level)) => modules.push((
+                String::from(module.trim()),
+                parse_level(module_level.trim())?,
+            )),
+            None => level = Some(parse_level(part)?),
+        }
+    }
+    Ok((level, modules))
+}
+
+// koko.log in the XDG state directory, or the local data directory where there is none
+pub fn default_file() -> Option<PathBuf> {
+    let proj_dirs = ProjectDirs::from("", "Erkki Seppälä", "koko")?;
+    // directories 3 doesn't know about the state directory
+    #[cfg(all(unix, not(target_os = "macos")))]
+    {
+        let state_home = std::env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| {
+                let home = std::env::var_os("HOME")?;
+                Some(Path::new(&home).join(".local/state"))
+            });
+        if let Some(state_home) = state_home {
+            return Some(state_home.join(proj_dirs.project_path()).join("koko.log"));
+        }
+    }
+    Some(proj_dirs.data_local_dir().join("koko.log"))
+}
+
+// whether anything would be logged at all
+pub fn enabled(level: LevelFilter, modules: &[(String, LevelFilter)]) -> bool {
+    level != LevelFilter::Off || modules.iter().any(|(_, level)| *level != LevelFilter::Off)
+}
+
+pub fn init(options: &Options) -> Result<(), Error> {
+    if let Some(dir) = options.file.parent() {
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(dir)?;
+        }
+    }
+
+    let roller: Box<dyn Roll> = if options.keep == 0 {
+        Box::new(DeleteRoller::new())
+    } else {
+        let pattern = format!("{}.{{}}", options.file.to_string_lossy());
+        Box::new(
+            FixedWindowRoller::builder()
+                .base(1)
+                .build(&pattern, options.keep)
+                .map_err(|err| Error::RotationError(err.to_string()))?,
+        )
+    };
+    let policy = CompoundPolicy::new(
+        Box::new(SizeTrigger::new(options.max_size_kb * 1024)),
+        roller,
+    );
+    let encoder: Box<dyn Encode> = match options.format {
+        LogFormat::Text => Box::new(PatternEncoder::new("{d} {l} {t} {m}{n}")),
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    };
+    let logfile = RollingFileAppender::builder()
+        .encoder(encoder)
+        .build(&options.file, Box::new(policy))?;
+
+    let config = options
+        .modules
+        .iter()
+        .fold(
+            Config::builder().appender(Appender::builder().build("logfile", Box::new(logfile))),
+            |config, (module, level)| config.logger(Logger::builder().build(module, *level)),
+        )
+        .build(Root::builder().appender("logfile").build(options.level))?;
+
+    log4rs::init_config(config)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_levels() {
+        assert_eq!(
+            parse_levels("debug, kodi_kontrol::ui::poll=off,kodi_kontrol::kodi_rpc=TRACE").unwrap(),
+            (
+                Some(LevelFilter::Debug),
+                vec![
+                    (String::from("kodi_kontrol::ui::poll"), LevelFilter::Off),
+                    (String::from("kodi_kontrol::kodi_rpc"), LevelFilter::Trace),
+                ]
+            )
+        );
+        assert_eq!(parse_levels("").unwrap(), (None, vec![]));
+        assert!(parse_levels("loud").is_err());
+        assert!(enabled(
+            LevelFilter::Off,
+            &[(String::from("kodi_kontrol::server"), LevelFilter::Info)]
+        ));
+        assert!(!enabled(LevelFilter::Off, &[]));
+    }
+}
@@ -0,0 +1,190 @@
+use crate::kodi_rpc_types::{GUIWindow, PlayerPropertyName, PlaylistId};
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Cannot read {}: {}", .0.to_string_lossy(), .1)]
+    ReadError(PathBuf, std::io::Error),
+
+    #[error("Pictures cannot be played together with audio or video ({})", .0.to_string_lossy())]
+    MixedPictures(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaType {
+    Audio,
+    Video,
+    Picture,
+}
+
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "aac", "ac3", "aiff", "alac", "ape", "dts", "flac", "m4a", "mka", "mp2", "mp3", "mpc", "oga",
+    "ogg", "opus", "wav", "wma", "wv",
+];
+
+const VIDEO_EXTENSIONS: &[&str] = &[
+    "3gp", "avi", "divx", "flv", "m2ts", "m4v", "mkv", "mov", "mp4", "mpeg", "mpg", "mts", "ogv",
+    "ts", "vob", "webm", "wmv",
+];
+
+const PICTURE_EXTENSIONS: &[&str] = &[
+    "bmp", "gif", "heic", "jpeg", "jpg", "png", "tga", "tif", "tiff", "webp",
+];
+
+impl MediaType {
+    // Kodi's playlists are fixed: 0 is audio, 1 is video, 2 is pictures
+    pub fn playlist_id(&self) -> PlaylistId {
+        match self {
+            MediaType::Audio => 0,
+            MediaType::Video => 1,
+            MediaType::Picture => 2,
+        }
+    }
+
+    pub fn window(&self) -> GUIWindow {
+        match self {
+            MediaType::Audio => GUIWindow::Visualisation,
+            MediaType::Video => GUIWindow::FullscreenVideo,
+            MediaType::Picture => GUIWindow::SlideShow,
+        }
+    }
+
+    // the property telling if something is still being played; the slideshow has none
+    pub fn stream_property(&self) -> Option<PlayerPropertyName> {
+        match self {
+            MediaType::Audio => Some(PlayerPropertyName::CurrentAudioStream),
+            MediaType::Video => Some(PlayerPropertyName::CurrentVideoStream),
+            MediaType::Picture => None,
+        }
+    }
+
+    // as in the "type" of Player.GetActivePlayers
+    pub fn from_player_type(type_: &str) -> Option<MediaType> {
+        match type_ {
+            "audio" => Some(MediaType::Audio),
+            "video" => Some(MediaType::Video),
+            "picture" => Some(MediaType::Picture),
+            _ => None,
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<MediaType> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        let extension = extension.as_str();
+        if AUDIO_EXTENSIONS.contains(&extension) {
+            Some(MediaType::Audio)
+        } else if VIDEO_EXTENSIONS.contains(&extension) {
+            Some(MediaType::Video)
+        } else if PICTURE_EXTENSIONS.contains(&extension) {
+            Some(MediaType::Picture)
+        } else {
+            None
+        }
+    }
+
+    // guess from the first bytes of the file, like file(1) or ffprobe would
+    fn from_header(header: &[u8]) -> Option<MediaType> {
+        let starts = |magic: &[u8]| header.starts_with(magic);
+        let at = |offset: usize, magic: &[u8]| {
+            header.len() >= offset + magic.len() && &header[offset..offset + magic.len()] == magic
+        };
+        if starts(b"\xff\xd8\xff")
+            || starts(b"\x89PNG")
+            || starts(b"GIF8")
+            || starts(b"BM")
+            || starts(b"II*\x00")
+            || starts(b"MM\x00*")
+            || (starts(b"RIFF") && at(8, b"WEBP"))
+        {
+            Some(MediaType::Picture)
+        } else if starts(b"ID3")
+            || starts(b"fLaC")
+            || starts(b"OggS")
+            || (starts(b"RIFF") && at(8, b"WAVE"))
+            || (at(4, b"ftyp") && at(8, b"M4A "))
+            || (header.len() >= 2 && header[0] == 0xff && header[1] & 0xe0 == 0xe0)
+        {
+            Some(MediaType::Audio)
+        } else if at(4, b"ftyp")
+            || starts(b"\x1a\x45\xdf\xa3")
+            || starts(b"\x00\x00\x01\xba")
+            || starts(b"FLV")
+            || (starts(b"RIFF") && at(8, b"AVI "))
+        {
+            Some(MediaType::Video)
+        } else {
+            None
+        }
+    }
+
+    // None when neither the extension nor the contents look like media
+    pub fn recognize(path: &Path) -> Result<Option<MediaType>, Error> {
+        if let Some(media_type) = MediaType::from_extension(path) {
+            return Ok(Some(media_type));
+        }
+        let mut header = Vec::new();
+        std::fs::File::open(path)
+            .and_then(|file| file.take(16).read_to_end(&mut header))
+            .map_err(|err| Error::ReadError(path.to_path_buf(), err))?;
+        Ok(MediaType::from_header(&header))
+    }
+
+    pub fn detect(path: &Path) -> Result<MediaType, Error> {
+        Ok(MediaType::recognize(path)?.unwrap_or_else(|| {
+            // before media types were a thing, everything was played as video
+            log::info!("Unknown media type for {:?}, assuming video", path);
+            MediaType::Video
+        }))
+    }
+
+    // the type of the playlist to use for all the given files; the video playlist
+    // plays audio as well, but pictures need a playlist of their own
+    pub fn detect_all<'a, I>(paths: I) -> Result<MediaType, Error>
+    where
+        I: IntoIterator<Item = &'a PathBuf>,
+    {
+        let mut result = None;
+        for path in paths {
+            let media_type = MediaType::detect(path)?;
+            result = match (result, media_type) {
+                (None, _) => Some(media_type),
+                (Some(previous), _) if previous == media_type => Some(media_type),
+                (Some(MediaType::Picture), _) | (_, MediaType::Picture) => {
+                    return Err(Error::MixedPictures(path.clone()))
+                }
+                _ => Some(MediaType::Video),
+            };
+        }
+        Ok(result.unwrap_or(MediaType::Video))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_header() {
+        assert_eq!(
+            MediaType::from_header(b"\xff\xd8\xff\xe0\x00\x10JFIF"),
+            Some(MediaType::Picture)
+        );
+        assert_eq!(
+            MediaType::from_header(b"ID3\x04\x00\x00"),
+            Some(MediaType::Audio)
+        );
+        assert_eq!(
+            MediaType::from_header(b"\x00\x00\x00\x20ftypM4A \x00"),
+            Some(MediaType::Audio)
+        );
+        assert_eq!(
+            MediaType::from_header(b"\x00\x00\x00\x20ftypisom\x00"),
+            Some(MediaType::Video)
+        );
+        assert_eq!(MediaType::from_header(b"hello"), None);
+    }
+}
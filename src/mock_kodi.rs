@@ -0,0 +1,681 @@
+// A pretend Kodi for trying out koko without one, e.g. in end-to-end tests: the HTTP and
+// WebSocket JSON-RPC for the calls koko makes. Opened items are actually fetched, then
+// "play" for item_duration each, with the notifications a real Kodi would send.
+use crate::ws_server;
+
+use futures::channel::mpsc;
+use hyper::http::{Request, StatusCode};
+use hyper::{client::conn::Builder, Body};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use url::Url;
+
+use actix_web::{web, App, HttpResponse, HttpServer};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    HyperError(#[from] hyper::Error),
+
+    #[error(transparent)]
+    WebSocketError(#[from] ws_server::Error),
+
+    #[error("Cannot fetch {}: {}", .0, .1)]
+    FetchError(String, String),
+}
+
+// what koko asks for with JSONRPC.Introspect and the GET of /jsonrpc
+const SCHEMA: &str = include_str!("../schema/introspect.json");
+
+const PLAYER_ID: u32 = 1;
+
+pub struct Options {
+    pub address: std::net::IpAddr,
+    pub http_port: u16,
+    // koko uses 9090, like Kodi's default
+    pub ws_port: u16,
+    // how long each item plays
+    pub item_duration: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Repeat {
+    Off,
+    One,
+    All,
+}
+
+#[derive(Debug)]
+struct Playing {
+    // -1 when a file was opened directly
+    playlist_id: i32,
+    position: i32,
+    file: String,
+    // the time at since, or at pausing
+    offset_ms: u32,
+    // None while paused, or while the file is being fetched
+    since: Option<Instant>,
+    loaded: bool,
+}
+
+impl Playing {
+    fn ms(&self) -> u32 {
+        self.offset_ms
+            + self
+                .since
+                .map_or(0, |since| since.elapsed().as_millis() as u32)
+    }
+
+    fn speed(&self) -> i32 {
+        if self.since.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+struct State {
+    playlists: HashMap<i32, Vec<String>>,
+    playing: Option<Playing>,
+    repeat: Repeat,
+    shuffled: bool,
+    // the WebSocket connections, for notifications
+    listeners: Vec<mpsc::UnboundedSender<Value>>,
+    fetched: Vec<String>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    item_ms: u32,
+}
+
+fn time(ms: u32) -> Value {
+    json!({
+        "hours": ms / 3_600_000,
+        "minutes": ms / 60_000 % 60,
+        "seconds": ms / 1000 % 60,
+        "milliseconds": ms % 1000,
+    })
+}
+
+fn ms_of_time(time: &Value) -> u32 {
+    let field = |name: &str| time[name].as_u64().unwrap_or(0) as u32;
+    ((field("hours") * 60 + field("minutes")) * 60 + field("seconds")) * 1000
+        + field("milliseconds")
+}
+
+fn label(file: &str) -> String {
+    String::from(file.rsplit('/').next().unwrap_or(file))
+}
+
+// JSON-RPC errors as Kodi has them
+const INVALID_PARAMS: (i64, &str) = (-32602, "Invalid params.");
+const METHOD_NOT_FOUND: (i64, &str) = (-32601, "Method not found.");
+const FAILED: (i64, &str) = (-32100, "Failed to execute method.");
+
+impl State {
+    fn notify(&mut self, method: &str, data: Value) {
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": { "sender": "xbmc", "data": data },
+        });
+        self.listeners
+            .retain(|listener| listener.unbounded_send(notification.clone()).is_ok());
+    }
+
+    fn notify_player(&mut self, method: &str) {
+        let (file, speed) = match &self.playing {
+            Some(playing) => (playing.file.clone(), playing.speed()),
+            None => return,
+        };
+        let data = json!({
+            "item": { "type": "unknown", "title": label(&file) },
+            "player": { "playerid": PLAYER_ID, "speed": speed },
+        });
+        self.notify(method, data);
+    }
+
+    fn notify_stop(&mut self, end: bool) {
+        self.notify(
+            "Player.OnStop",
+            json!({ "item": { "type": "unknown" }, "end": end }),
+        );
+    }
+
+    fn item(&self, playlist_id: i32, position: i32) -> Option<String> {
+        self.playlists
+            .get(&playlist_id)
+            .and_then(|items| items.get(position as usize))
+            .cloned()
+    }
+}
+
+impl Shared {
+    // starts loading the item; it plays once fetched
+    fn open(self: &Arc<Self>, state: &mut State, playlist_id: i32, position: i32, file: String) {
+        state.playing = Some(Playing {
+            playlist_id,
+            position,
+            file: file.clone(),
+            offset_ms: 0,
+            since: None,
+            loaded: false,
+        });
+        let shared = self.clone();
+        tokio::spawn(async move {
+            let result = fetch(&file).await;
+            let mut state = shared.state.lock().unwrap();
+            let current =
+                matches!(&state.playing, Some(playing) if playing.file == file && !playing.loaded);
+            match result {
+                Ok(bytes) => {
+                    log::info!("Fetched {} bytes from {}", bytes, file);
+                    state.fetched.push(file);
+                }
+                Err(err) => {
+                    log::error!("{}", err);
+                    if current {
+                        state.playing = None;
+                        state.notify_stop(false);
+                    }
+                    return;
+                }
+            }
+            if current {
+                if let Some(playing) = state.playing.as_mut() {
+                    playing.loaded = true;
+                    playing.since = Some(Instant::now());
+                }
+                state.notify_player("Player.OnPlay");
+                state.notify_player("Player.OnAVStart");
+            }
+        });
+    }
+
+    // ends the item that has played long enough and goes on to the next one
+    fn tick(self: &Arc<Self>) {
+        let mut state = self.state.lock().unwrap();
+        let (playlist_id, position, file) = match &state.playing {
+            Some(playing) if playing.loaded && playing.ms() >= self.item_ms => {
+                (playing.playlist_id, playing.position, playing.file.clone())
+            }
+            _ => return,
+        };
+        state.playing = None;
+        state.notify_stop(true);
+        let count = state
+            .playlists
+            .get(&playlist_id)
+            .map_or(0, |items| items.len()) as i32;
+        let next = match state.repeat {
+            Repeat::One => Some((position, file)),
+            _ if playlist_id < 0 => None,
+            Repeat::All if position + 1 >= count => state.item(playlist_id, 0).map(|x| (0, x)),
+            _ => state
+                .item(playlist_id, position + 1)
+                .map(|x| (position + 1, x)),
+        };
+        if let Some((position, file)) = next {
+            self.open(&mut state, playlist_id, position, file);
+        }
+    }
+
+    fn call(self: &Arc<Self>, method: &str, params: &Value) -> Result<Value, (i64, &'static str)> {
+        let mut state = self.state.lock().unwrap();
+        let item_ms = self.item_ms;
+        match method {
+            "JSONRPC.Ping" => Ok(json!("pong")),
+            "JSONRPC.Version" => Ok(json!({ "version": { "major": 12, "minor": 4, "patch": 0 } })),
+            "JSONRPC.Introspect" => Ok(serde_json::from_str(SCHEMA).expect("Invalid schema")),
+            "Player.GetPlayers" => Ok(json!([{
+                "name": "VideoPlayer", "playercoretype": "video", "playsaudio": true,
+                "playsvideo": true, "type": "video"
+            }])),
+            "Player.GetActivePlayers" => Ok(match state.playing {
+                Some(_) => {
+                    json!([{ "playerid": PLAYER_ID, "playertype": "internal", "type": "video" }])
+                }
+                None => json!([]),
+            }),
+            "Player.Open" => {
+                let item = &params["item"];
+                let (playlist_id, position, file) =
+                    match (item["file"].as_str(), item["playlistid"].as_i64()) {
+                        (Some(file), _) => (-1, -1, String::from(file)),
+                        (None, Some(playlist_id)) => {
+                            let playlist_id = playlist_id as i32;
+                            let position = item["position"].as_i64().unwrap_or(0) as i32;
+                            match state.item(playlist_id, position) {
+                                Some(file) => (playlist_id, position, file),
+                                None => return Err(INVALID_PARAMS),
+                            }
+                        }
+                        (None, None) => return Err(INVALID_PARAMS),
+                    };
+                match params["options"]["repeat"].as_str() {
+                    Some("one") => state.repeat = Repeat::One,
+                    Some("all") => state.repeat = Repeat::All,
+                    Some(_) => state.repeat = Repeat::Off,
+                    None => (),
+                }
+                if let Some(shuffled) = params["options"]["shuffled"].as_bool() {
+                    state.shuffled = shuffled;
+                }
+                if state.playing.take().is_some() {
+                    state.notify_stop(false);
+                }
+                self.open(&mut state, playlist_id, position, file);
+                Ok(json!("OK"))
+            }
+            "Player.Stop" => {
+                if state.playing.take().is_some() {
+                    state.notify_stop(false);
+                }
+                Ok(json!("OK"))
+            }
+            "Player.PlayPause" => {
+                let playing = state.playing.as_mut().ok_or(FAILED)?;
+                let play = match &params["play"] {
+                    Value::Bool(play) => *play,
+                    _ => playing.since.is_none(),
+                };
+                if play != playing.since.is_some() && playing.loaded {
+                    playing.offset_ms = playing.ms();
+                    playing.since = if play { Some(Instant::now()) } else { None };
+                    state.notify_player(if play {
+                        "Player.OnResume"
+                    } else {
+                        "Player.OnPause"
+                    });
+                }
+                Ok(json!({ "speed": state.playing.as_ref().map_or(0, |x| x.speed()) }))
+            }
+            "Player.Seek" => {
+                let playing = state.playing.as_mut().ok_or(FAILED)?;
+                let ms = playing.ms() as i64;
+                let value = &params["value"];
+                let target = if let Some(seconds) = value["seconds"].as_i64() {
+                    ms + seconds * 1000
+                } else if let Some(percentage) = value["percentage"].as_f64() {
+                    (percentage / 100.0 * item_ms as f64) as i64
+                } else if value["time"].is_object() {
+                    ms_of_time(&value["time"]) as i64
+                } else {
+                    match value["step"].as_str().or_else(|| value.as_str()) {
+                        Some("smallforward") => ms + 30_000,
+                        Some("smallbackward") => ms - 30_000,
+                        Some("bigforward") => ms + 600_000,
+                        Some("bigbackward") => ms - 600_000,
+                        _ => return Err(INVALID_PARAMS),
+                    }
+                };
+                playing.offset_ms = target.clamp(0, item_ms as i64) as u32;
+                if playing.since.is_some() {
+                    playing.since = Some(Instant::now());
+                }
+                let ms = playing.offset_ms;
+                state.notify_player("Player.OnSeek");
+                Ok(json!({
+                    "percentage": ms as f64 * 100.0 / item_ms as f64,
+                    "time": time(ms),
+                    "totaltime": time(item_ms),
+                }))
+            }
+            "Player.GoTo" => {
+                let (playlist_id, position) = match &state.playing {
+                    Some(playing) if playing.playlist_id >= 0 => {
+                        (playing.playlist_id, playing.position)
+                    }
+                    _ => return Err(FAILED),
+                };
+                let position = match &params["to"] {
+                    Value::String(to) if to == "next" => position + 1,
+                    Value::String(to) if to == "previous" => position - 1,
+                    Value::Number(to) => to.as_i64().unwrap_or(0) as i32,
+                    _ => return Err(INVALID_PARAMS),
+                };
+                let file = state.item(playlist_id, position).ok_or(FAILED)?;
+                self.open(&mut state, playlist_id, position, file);
+                Ok(json!("OK"))
+            }
+            "Player.SetRepeat" => {
+                state.repeat = match params["repeat"].as_str() {
+                    Some("one") => Repeat::One,
+                    Some("all") => Repeat::All,
+                    Some("off") => Repeat::Off,
+                    // cycle
+                    _ => match state.repeat {
+                        Repeat::Off => Repeat::All,
+                        Repeat::All => Repeat::One,
+                        Repeat::One => Repeat::Off,
+                    },
+                };
+                Ok(json!("OK"))
+            }
+            "Player.SetShuffle" => {
+                state.shuffled = match &params["shuffle"] {
+                    Value::Bool(shuffle) => *shuffle,
+                    _ => !state.shuffled,
+                };
+                Ok(json!("OK"))
+            }
+            "Player.GetProperties" => {
+                let playing = state.playing.as_ref();
+                let ms = playing.map_or(0, |playing| playing.ms());
+                let codec = if playing.is_some() { "h264" } else { "" };
+                let repeat = match state.repeat {
+                    Repeat::Off => "off",
+                    Repeat::One => "one",
+                    Repeat::All => "all",
+                };
+                let mut properties = serde_json::Map::new();
+                for name in params["properties"].as_array().ok_or(INVALID_PARAMS)? {
+                    let name = name.as_str().ok_or(INVALID_PARAMS)?;
+                    let value = match name {
+                        "type" => json!("video"),
+                        "speed" => json!(playing.map_or(0, |x| x.speed())),
+                        "time" => time(ms),
+                        "totaltime" => time(if playing.is_some() { item_ms } else { 0 }),
+                        "percentage" => json!(ms as f64 * 100.0 / item_ms as f64),
+                        "playlistid" => json!(playing.map_or(-1, |x| x.playlist_id)),
+                        "position" => json!(playing.map_or(-1, |x| x.position)),
+                        "repeat" => json!(repeat),
+                        "shuffled" => json!(state.shuffled),
+                        "currentvideostream" => json!({
+                            "codec": codec, "height": 1080, "width": 1920, "index": 0,
+                            "language": "", "name": ""
+                        }),
+                        "currentaudiostream" => json!({
+                            "codec": codec, "bitrate": 0, "channels": 2, "index": 0,
+                            "language": "", "name": ""
+                        }),
+                        "videostreams" | "audiostreams" | "subtitles" => json!([]),
+                        _ => json!(false),
+                    };
+                    properties.insert(String::from(name), value);
+                }
+                Ok(Value::Object(properties))
+            }
+            "Player.GetItem" => {
+                let playing = state.playing.as_ref().ok_or(FAILED)?;
+                Ok(json!({ "item": {
+                    "label": label(&playing.file), "file": playing.file, "type": "unknown"
+                }}))
+            }
+            "Playlist.Add" => {
+                let playlist_id = params["playlistid"].as_i64().ok_or(INVALID_PARAMS)? as i32;
+                let items = match &params["item"] {
+                    Value::Array(items) => items.clone(),
+                    item => vec![item.clone()],
+                };
+                let files = items
+                    .iter()
+                    .map(|item| item["file"].as_str().map(String::from))
+                    .collect::<Option<Vec<String>>>()
+                    .ok_or(INVALID_PARAMS)?;
+                state
+                    .playlists
+                    .entry(playlist_id)
+                    .or_default()
+                    .extend(files);
+                Ok(json!("OK"))
+            }
+            "Playlist.Clear" => {
+                let playlist_id = params["playlistid"].as_i64().ok_or(INVALID_PARAMS)? as i32;
+                state.playlists.remove(&playlist_id);
+                Ok(json!("OK"))
+            }
+            "Playlist.GetItems" => {
+                let playlist_id = params["playlistid"].as_i64().ok_or(INVALID_PARAMS)? as i32;
+                let items: Vec<Value> = state
+                    .playlists
+                    .get(&playlist_id)
+                    .map(|files| files.as_slice())
+                    .unwrap_or(&[])
+                    .iter()
+                    .map(|file| json!({ "label": label(file), "file": file, "type": "unknown" }))
+                    .collect();
+                let total = items.len();
+                Ok(
+                    json!({ "items": items, "limits": { "start": 0, "end": total, "total": total } }),
+                )
+            }
+            "GUI.ActivateWindow"
+            | "GUI.ShowNotification"
+            | "Input.ExecuteAction"
+            | "Input.SendText" => {
+                log::info!("{} {}", method, params);
+                Ok(json!("OK"))
+            }
+            "GUI.GetProperties" => Ok(json!({ "currentwindow": { "id": 10000, "label": "Home" } })),
+            "XBMC.GetInfoLabels" => {
+                let mut labels = serde_json::Map::new();
+                for name in params["labels"].as_array().ok_or(INVALID_PARAMS)? {
+                    labels.insert(String::from(name.as_str().unwrap_or("")), json!(""));
+                }
+                Ok(Value::Object(labels))
+            }
+            method if method.starts_with("Input.") => Ok(json!("OK")),
+            _ => Err(METHOD_NOT_FOUND),
+        }
+    }
+
+    // a single JSON-RPC request to a response
+    fn respond(self: &Arc<Self>, request: &Value) -> Value {
+        let method = request["method"].as_str().unwrap_or("");
+        log::debug!("Request: {}", request);
+        let mut response = match self.call(method, &request["params"]) {
+            Ok(result) => json!({ "result": result }),
+            Err((code, message)) => {
+                log::warn!("{} failed: {}", method, message);
+                json!({ "error": { "code": code, "message": message } })
+            }
+        };
+        response["jsonrpc"] = json!("2.0");
+        response["id"] = request["id"].clone();
+        response
+    }
+}
+
+// the whole file, like a player would; only the size is of interest
+async fn fetch(file: &str) -> Result<usize, Error> {
+    let error = |message: String| Error::FetchError(String::from(file), message);
+    let url = Url::parse(file).map_err(|err| error(err.to_string()))?;
+    let host = url
+        .host_str()
+        .ok_or_else(|| error(String::from("no host")))?;
+    let stream = TcpStream::connect((host, url.port().unwrap_or(80))).await?;
+    let (mut request_sender, connection) =
+        Builder::new().handshake::<TcpStream, Body>(stream).await?;
+    tokio::spawn(async move {
+        if let Err(err) = connection.await {
+            log::error!("Error in connection: {}", err);
+        }
+    });
+    let request = Request::builder()
+        .uri(url.path())
+        .header("Host", host)
+        .method("GET")
+        .body(Body::from(""))
+        .map_err(|err| error(err.to_string()))?;
+    let response = request_sender.send_request(request).await?;
+    if response.status() != StatusCode::OK {
+        return Err(error(format!("HTTP error {}", response.status())));
+    }
+    Ok(hyper::body::to_bytes(response.into_body()).await?.len())
+}
+
+async fn http_jsonrpc(shared: web::Data<Arc<Shared>>, body: web::Bytes) -> HttpResponse {
+    match serde_json::from_slice::<Value>(&body) {
+        Ok(request) => HttpResponse::Ok().json(shared.respond(&request)),
+        Err(err) => HttpResponse::BadRequest().body(err.to_string()),
+    }
+}
+
+// koko does a GET first to find its own address
+async fn http_schema() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(SCHEMA)
+}
+
+impl ws_server::Handler for Shared {
+    fn connect(self: &Arc<Self>) -> mpsc::UnboundedReceiver<Value> {
+        let (listener, notifications) = mpsc::unbounded();
+        self.state.lock().unwrap().listeners.push(listener);
+        notifications
+    }
+
+    fn respond(self: &Arc<Self>, request: &Value) -> Value {
+        Shared::respond(self, request)
+    }
+}
+
+pub struct MockKodi {
+    shared: Arc<Shared>,
+    http_addr: std::net::SocketAddr,
+    ws_addr: std::net::SocketAddr,
+}
+
+impl MockKodi {
+    // runs until the (actix) runtime shuts down
+    pub async fn start(options: Options) -> Result<MockKodi, Error> {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                playlists: HashMap::new(),
+                playing: None,
+                repeat: Repeat::Off,
+                shuffled: false,
+                listeners: Vec::new(),
+                fetched: Vec::new(),
+            }),
+            item_ms: options.item_duration.as_millis() as u32,
+        });
+
+        let server = HttpServer::new({
+            let shared = shared.clone();
+            move || {
+                App::new()
+                    .app_data(web::Data::new(shared.clone()))
+                    .route("/jsonrpc", web::post().to(http_jsonrpc))
+                    .route("/jsonrpc", web::get().to(http_schema))
+            }
+        })
+        .workers(1)
+        .bind((options.address, options.http_port))?;
+        let http_addr = server.addrs()[0];
+        let server = server.run();
+        actix_rt::spawn(async move {
+            if let Err(err) = server.await {
+                log::error!("HTTP server failed: {}", err);
+            }
+        });
+
+        let ws_addr = ws_server::start(options.address, options.ws_port, shared.clone()).await?;
+
+        tokio::spawn({
+            let shared = shared.clone();
+            async move {
+                let mut interval = tokio::time::interval(Duration::from_millis(100));
+                loop {
+                    interval.tick().await;
+                    shared.tick();
+                }
+            }
+        });
+
+        Ok(MockKodi {
+            shared,
+            http_addr,
+            ws_addr,
+        })
+    }
+
+    pub fn http_addr(&self) -> std::net::SocketAddr {
+        self.http_addr
+    }
+
+    pub fn ws_addr(&self) -> std::net::SocketAddr {
+        self.ws_addr
+    }
+
+    // the URLs of the items fetched so far
+    pub fn fetched(&self) -> Vec<String> {
+        self.shared.state.lock().unwrap().fetched.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::StreamExt;
+
+    #[test]
+    fn test_time() {
+        let ms = 3_723_004;
+        assert_eq!(
+            time(ms),
+            json!({ "hours": 1, "minutes": 2, "seconds": 3, "milliseconds": 4 })
+        );
+        assert_eq!(ms_of_time(&time(ms)), ms);
+    }
+
+    // nothing listens on port 1, so the item fails to load and stops
+    #[tokio::test]
+    async fn test_playlist() {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                playlists: HashMap::new(),
+                playing: None,
+                repeat: Repeat::Off,
+                shuffled: false,
+                listeners: Vec::new(),
+                fetched: Vec::new(),
+            }),
+            item_ms: 1000,
+        });
+        let (listener, mut notifications) = mpsc::unbounded();
+        shared.state.lock().unwrap().listeners.push(listener);
+        let files = [
+            "http://127.0.0.1:1/file/a.mkv",
+            "http://127.0.0.1:1/file/b.mkv",
+        ];
+        let items: Vec<Value> = files.iter().map(|file| json!({ "file": file })).collect();
+        shared
+            .call("Playlist.Add", &json!({ "playlistid": 1, "item": items }))
+            .unwrap();
+        let result = shared
+            .call("Playlist.GetItems", &json!({ "playlistid": 1 }))
+            .unwrap();
+        assert_eq!(result["limits"]["total"], 2);
+        assert_eq!(result["items"][1]["label"], "b.mkv");
+
+        let open = json!({ "item": { "playlistid": 1, "position": 1 } });
+        assert_eq!(shared.call("Player.Open", &open).unwrap(), "OK");
+        let players = shared.call("Player.GetActivePlayers", &json!({})).unwrap();
+        assert_eq!(players[0]["playerid"], PLAYER_ID);
+        let open = json!({ "item": { "playlistid": 1, "position": 2 } });
+        assert_eq!(shared.call("Player.Open", &open), Err(INVALID_PARAMS));
+
+        let notification = notifications.next().await.unwrap();
+        assert_eq!(notification["method"], "Player.OnStop");
+        assert_eq!(notification["params"]["data"]["end"], false);
+        assert_eq!(
+            shared.call("Player.GetActivePlayers", &json!({})).unwrap(),
+            json!([])
+        );
+        assert!(shared.state.lock().unwrap().fetched.is_empty());
+    }
+}
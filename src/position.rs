@@ -0,0 +1,167 @@
+use crate::kodi_rpc_types::{GlobalTime, Seek};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Failed to parse position {}: {}", .0, .1)]
+    ParseError(String, String),
+}
+
+// A position within an item, as given on the command line
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Position {
+    Milliseconds(u32),
+    Percentage(f64),
+}
+
+impl Position {
+    pub fn to_seek(&self) -> Seek {
+        match self {
+            Position::Milliseconds(ms) => Seek::AbsoluteTime {
+                time: GlobalTime::from_milliseconds(*ms),
+            },
+            Position::Percentage(percentage) => Seek::AbsolutePercentage {
+                percentage: *percentage,
+            },
+        }
+    }
+
+    // percentages need to know the length of the item
+    pub fn as_milliseconds(&self, total_ms: u32) -> u32 {
+        match self {
+            Position::Milliseconds(ms) => *ms,
+            Position::Percentage(percentage) => (total_ms as f64 * percentage / 100.0) as u32,
+        }
+    }
+}
+
+// 1h4m3s -> 1*3600 + 4*60 + 3
+fn parse_units(str: &str) -> Result<u32, String> {
+    enum State {
+        Begin,
+        Value(u32),
+        Mul,
+    }
+    let mut state = State::Begin;
+    let mut seconds = 0u32;
+    let too_long = || String::from("Too long");
+    for char in str.chars() {
+        match char {
+            '0'..='9' => {
+                let value = match state {
+                    State::Value(value) => value,
+                    _ => 0,
+                };
+                let value = value
+                    .checked_mul(10)
+                    .and_then(|value| value.checked_add(char.to_digit(10).unwrap()))
+                    .ok_or_else(too_long)?;
+                state = State::Value(value);
+            }
+            'h' | 'm' | 's' => {
+                let mul = match char {
+                    'h' => 3600,
+                    'm' => 60,
+                    _ => 1,
+                };
+                let value = match state {
+                    State::Value(value) => value,
+                    _ => return Err(format!("Unexpected '{}'", char)),
+                };
+                seconds = value
+                    .checked_mul(mul)
+                    .and_then(|value| value.checked_add(seconds))
+                    .ok_or_else(too_long)?;
+                state = State::Mul;
+            }
+            char => return Err(format!("Invalid character: {}", char)),
+        }
+    }
+    match state {
+        State::Mul => seconds.checked_mul(1000).ok_or_else(too_long),
+        _ => Err(String::from("Expected time specifier at the end")),
+    }
+}
+
+// [[hh:]mm:]ss[.ms]
+fn parse_clock(str: &str) -> Result<u32, String> {
+    let fields: Vec<&str> = str.split(':').collect();
+    if fields.len() > 3 {
+        return Err(String::from("Too many fields"));
+    }
+    let mut ms = 0f64;
+    for (index, field) in fields.iter().enumerate() {
+        let last = index == fields.len() - 1;
+        let value: f64 = if last {
+            field
+                .parse()
+                .map_err(|_| format!("Invalid seconds: {}", field))?
+        } else {
+            field
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid field: {}", field))? as f64
+        };
+        if value < 0.0 || !value.is_finite() || (index > 0 && value >= 60.0) {
+            return Err(format!("Out of range: {}", field));
+        }
+        ms = ms * 60.0 + value * 1000.0;
+    }
+    if ms.round() > u32::MAX as f64 {
+        return Err(String::from("Too long"));
+    }
+    Ok(ms.round() as u32)
+}
+
+impl std::str::FromStr for Position {
+    type Err = Error;
+
+    fn from_str(str: &str) -> Result<Position, Error> {
+        let error = |message: String| Error::ParseError(String::from(str), message);
+        if let Some(percentage) = str.strip_suffix('%') {
+            match percentage.parse::<f64>() {
+                Ok(percentage) if (0.0..=100.0).contains(&percentage) => {
+                    Ok(Position::Percentage(percentage))
+                }
+                _ => Err(error(String::from(
+                    "Expected a percentage between 0 and 100",
+                ))),
+            }
+        } else if str.ends_with(|char| matches!(char, 'h' | 'm' | 's')) {
+            parse_units(str).map(Position::Milliseconds).map_err(error)
+        } else {
+            parse_clock(str).map(Position::Milliseconds).map_err(error)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let ms = |str: &str| match str.parse::<Position>() {
+            Ok(Position::Milliseconds(ms)) => Some(ms),
+            _ => None,
+        };
+        assert_eq!(ms("1h2m3s"), Some(3723000));
+        assert_eq!(ms("5m"), Some(300000));
+        assert_eq!(ms("90"), Some(90000));
+        assert_eq!(ms("1.5"), Some(1500));
+        assert_eq!(ms("2:03"), Some(123000));
+        assert_eq!(ms("1:02:03.250"), Some(3723250));
+        assert_eq!(ms("1:60"), None);
+        assert_eq!(ms("m5"), None);
+        assert_eq!(ms("abc"), None);
+        // more milliseconds than fit
+        assert_eq!(ms("5000000s"), None);
+        assert_eq!(ms("99999999999999h"), None);
+        assert_eq!(ms("5000000"), None);
+        assert_eq!(
+            "12.5%".parse::<Position>().ok(),
+            Some(Position::Percentage(12.5))
+        );
+        assert!("150%".parse::<Position>().is_err());
+    }
+}
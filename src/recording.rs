@@ -0,0 +1,76 @@
+use crate::jsonl;
+
+use async_jsonrpc_client::{Output, Params};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use std::path::Path;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    JsonlError(#[from] jsonl::Error),
+}
+
+// One line of a recording, which is a JSON object per line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Entry {
+    // a call koko made and Kodi's answer to it; error is Kodi's error object
+    Call {
+        method: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        params: Option<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        result: Option<Value>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<Value>,
+    },
+    // as Kodi sent it, or as polling made it up
+    Notification(Value),
+}
+
+pub fn load(path: &Path) -> Result<Vec<Entry>, Error> {
+    Ok(jsonl::load(path)?)
+}
+
+// Writes the JSON-RPC traffic of a session to a file as it happens. Failing to write is
+// logged; the session goes on.
+#[derive(Debug)]
+pub struct Recorder {
+    writer: jsonl::Writer,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> Result<Recorder, Error> {
+        Ok(Recorder {
+            writer: jsonl::Writer::create(path)?,
+        })
+    }
+
+    pub fn record(&self, entry: &Entry) {
+        if let Err(err) = self.writer.write(entry) {
+            log::error!("Failed to record: {}", err);
+        }
+    }
+
+    pub fn call(&self, method: &str, params: Option<&Params>, output: &Output) {
+        let (result, error) = match output {
+            Output::Success(success) => (Some(success.result.clone()), None),
+            Output::Failure(failure) => (None, serde_json::to_value(&failure.error).ok()),
+        };
+        self.record(&Entry::Call {
+            method: String::from(method),
+            params: params.and_then(|params| serde_json::to_value(params).ok()),
+            result,
+            error,
+        });
+    }
+
+    pub fn notification(&self, notification: &Value) {
+        self.record(&Entry::Notification(notification.clone()));
+    }
+}
@@ -2,7 +2,9 @@ use std::sync::Mutex;
 
 use thiserror::Error;
 
-use crate::{error, exit, kodi_control, kodi_rpc, version::get_version};
+use crate::{
+    error, exit, kodi_control, kodi_rpc, position::Position, recording, version::get_version,
+};
 
 use url::Url;
 
@@ -63,6 +65,9 @@ pub struct AppData {
     pub kodi_auth: Option<(String, String)>,
     pub files: HashMap<String, PathBuf>,
     pub urls_order: HashMap<String, usize>,
+    pub urls_start: HashMap<String, Position>,
+    // a file in files that is played in the background, not as part of the playlist
+    pub background_audio: Option<String>,
     pub previously_logged_file: Option<String>,
 }
 
@@ -70,6 +75,42 @@ pub fn make_app_data_holder(app_data: AppData) -> AppDataHolder {
     return web::Data::new(Mutex::new(app_data));
 }
 
+// Adds files to serve while the server is running, for koko watch
+#[derive(Clone)]
+pub struct ServedFiles {
+    app_data: AppDataHolder,
+    server_addr: std::net::SocketAddr,
+}
+
+impl std::fmt::Debug for ServedFiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServedFiles")
+            .field("server_addr", &self.server_addr)
+            .finish()
+    }
+}
+
+impl ServedFiles {
+    // the URL Kodi can fetch the file from; the name is the file stem, numbered if taken
+    pub fn add(&self, path: PathBuf) -> Result<Url, Error> {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut app_data = self.app_data.lock().unwrap();
+        let name = (1..)
+            .map(|count| match count {
+                1 => stem.clone(),
+                count => format!("{} #{}", stem, count),
+            })
+            .find(|name| !app_data.files.contains_key(name))
+            .unwrap();
+        let url = url_for_file(self.server_addr, &name)?;
+        app_data.files.insert(name, path);
+        Ok(url)
+    }
+}
+
 pub fn configure(cfg: &mut web::ServiceConfig, app_data: AppDataHolder) {
     cfg.app_data(app_data)
         .route("/", web::get().to(info_page))
@@ -112,11 +153,16 @@ pub enum Error {
     // TODO: have kodi_rpc use its own error type.
     #[error(transparent)]
     KokoError(#[from] error::Error),
+
+    #[error(transparent)]
+    RecordingError(#[from] recording::Error),
 }
 
 #[derive(Debug)]
 pub struct Session {
     rpc_handler_done_rx: tokio::sync::oneshot::Receiver<Result<(), error::Error>>,
+    // None without a server, i.e. in remote mode
+    served_files: Option<ServedFiles>,
 }
 
 impl Session {
@@ -124,6 +170,7 @@ impl Session {
     pub async fn new(
         app_data: AppDataHolder,
         kodi_port: u16,
+        kodi_ws_port: u16,
         http_server_port: u16,
         result: tokio::sync::oneshot::Sender<Session>,
         exit: exit::Exit,
@@ -139,21 +186,22 @@ impl Session {
         )?;
         let wsurl = Url::parse(
             format!(
-                "ws://{}:9090/jsonrpc",
-                app_data.lock().unwrap().kodi_address
+                "ws://{}:{}/jsonrpc",
+                app_data.lock().unwrap().kodi_address,
+                kodi_ws_port
             )
             .as_str(),
         )?;
         let auth = app_data.lock().unwrap().kodi_auth.clone();
         let jsonrpc_info = kodi_rpc::jsonrpc_get(&url, &auth).await?;
 
-        let mut jsonrpc_session: kodi_rpc::WsJsonRPCSession = kodi_rpc::connect(&wsurl).await?;
+        let mut jsonrpc_session: kodi_rpc::WsJsonRPCSession =
+            kodi_rpc::connect_any(&wsurl, &url, &auth).await?;
+        if let Some(path) = &kodi_control_args.record {
+            jsonrpc_session.record(recording::Recorder::create(path)?);
+        }
 
-        // let introspect = ws_jsonrpc_introspect(&mut self.jsonrpc_session).await?;
-        // log::debug!("introspect: {}", introspect);
-        // let mut file = std::fs::File::create("introspect.json").expect("create failed");
-        // file.write_all(introspect.to_string().as_bytes())
-        //     .expect("write failed");
+        kodi_rpc::detect_capabilities(&mut jsonrpc_session).await;
 
         let players = kodi_rpc::get_players(&mut jsonrpc_session).await?;
         log::debug!("players: {}", players);
@@ -165,6 +213,8 @@ impl Session {
 
         let files = app_data.lock().unwrap().files.clone();
         let urls_order = app_data.lock().unwrap().urls_order.clone();
+        let background_audio = app_data.lock().unwrap().background_audio.clone();
+        let urls_start = app_data.lock().unwrap().urls_start.clone();
 
         let (rpc_handler_done_tx, rpc_handler_done_rx) = tokio::sync::oneshot::channel();
         let (stop_server_tx, stop_server_rx) = tokio::sync::oneshot::channel();
@@ -172,19 +222,37 @@ impl Session {
 
         tokio::spawn({
             let exit = exit.clone();
+            let app_data = app_data.clone();
             async move {
                 let server_info = server_info_rx.await.expect("Failed to receive server_info");
                 let mut ordered_urls: Vec<(usize, Url)> = files
+                    .keys()
+                    .filter_map(|url| {
+                        urls_order.get(url).map(|order| {
+                            (
+                                *order,
+                                url_for_file(server_info, url)
+                                    .expect("Failed to create URL for file"),
+                            )
+                        })
+                    })
+                    .collect();
+                ordered_urls.sort();
+                let urls: Vec<Url> = ordered_urls.into_iter().map(|(_k, v)| v).collect();
+                let mut kodi_control_args = kodi_control_args;
+                kodi_control_args.kodi_http = Some((url, auth));
+                kodi_control_args.background_audio = background_audio.map(|url| {
+                    url_for_file(server_info, &url).expect("Failed to create URL for file")
+                });
+                kodi_control_args.starts = urls_start
                     .iter()
-                    .map(|(url, _file)| {
+                    .map(|(url, start)| {
                         (
-                            urls_order.get(url).unwrap().clone(),
                             url_for_file(server_info, url).expect("Failed to create URL for file"),
+                            *start,
                         )
                     })
                     .collect();
-                ordered_urls.sort();
-                let urls: Vec<Url> = ordered_urls.into_iter().map(|(_k, v)| v).collect();
 
                 let (sigint_tx, sigint_rx) = mpsc::channel(1);
                 tokio::spawn(handle_ctrl_c(sigint_tx));
@@ -201,6 +269,10 @@ impl Session {
 
                 let session = Session {
                     rpc_handler_done_rx,
+                    served_files: Some(ServedFiles {
+                        app_data,
+                        server_addr: server_info,
+                    }),
                 };
 
                 result
@@ -224,6 +296,57 @@ impl Session {
         Ok(())
     }
 
+    // like new, but controls the media Kodi is already playing, so there's no server to run;
+    // will return once the rpc_handler has finished
+    pub async fn new_remote(
+        kodi_address: std::net::IpAddr,
+        kodi_port: u16,
+        kodi_ws_port: u16,
+        kodi_auth: Option<(String, String)>,
+        result: tokio::sync::oneshot::Sender<Session>,
+        exit: exit::Exit,
+        kodi_control_args: kodi_control::Args,
+    ) -> Result<(), Error> {
+        let url = Url::parse(format!("http://{}:{}/jsonrpc", kodi_address, kodi_port).as_str())?;
+        let wsurl = Url::parse(format!("ws://{}:{}/jsonrpc", kodi_address, kodi_ws_port).as_str())?;
+        let mut jsonrpc_session: kodi_rpc::WsJsonRPCSession =
+            kodi_rpc::connect_any(&wsurl, &url, &kodi_auth).await?;
+        if let Some(path) = &kodi_control_args.record {
+            jsonrpc_session.record(recording::Recorder::create(path)?);
+        }
+        kodi_rpc::detect_capabilities(&mut jsonrpc_session).await;
+
+        let (rpc_handler_done_tx, rpc_handler_done_rx) = tokio::sync::oneshot::channel();
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+
+        let (sigint_tx, sigint_rx) = mpsc::channel(1);
+        tokio::spawn(handle_ctrl_c(sigint_tx));
+
+        tokio::task::spawn(kodi_control::rpc_handler(
+            jsonrpc_session,
+            vec![],
+            sigint_rx,
+            stop_tx,
+            rpc_handler_done_tx,
+            exit.clone(),
+            kodi_control_args,
+        ));
+
+        result
+            .send(Session {
+                rpc_handler_done_rx,
+                served_files: None,
+            })
+            .expect("Failed to send result to caller");
+
+        // resolves on ctrl-c, or with an error once rpc_handler is gone
+        let _ = stop_rx.await;
+
+        exit.signal();
+
+        Ok(())
+    }
+
     #[rustfmt::skip::macros(select)]
     async fn run_server(
         app_data: AppDataHolder,
@@ -252,6 +375,11 @@ impl Session {
         }
         Ok(())
     }
+
+    pub fn served_files(&self) -> Option<ServedFiles> {
+        self.served_files.clone()
+    }
+
     pub async fn finish(self: Self) -> Result<(), error::Error> {
         &self
             .rpc_handler_done_rx
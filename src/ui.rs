@@ -1,14 +1,26 @@
 use cursive::traits::*;
 use cursive::view::Margins;
-use cursive::views::{Button, Dialog, DummyView, LinearLayout, OnEventView, ProgressBar, TextView};
-use cursive::{Cursive, CursiveExt};
-
-use crate::{kodi_control, kodi_control::KodiControl, kodi_rpc_types, ui_seek::UiSeek, version};
-
-use crate::{error, exit, util};
+use cursive::views::{
+    Button, Dialog, DummyView, EditView, LinearLayout, OnEventView, SelectView, TextView,
+};
+use cursive::{Cursive, CursiveExt, Vec2};
+
+use crate::{
+    config, keymap, kodi_api, kodi_control,
+    kodi_control::KodiControl,
+    kodi_rpc_types,
+    ui_chapters::ChapterMarks,
+    ui_passthrough::{Passthrough, PassthroughInput},
+    ui_seek::UiSeek,
+    ui_seekbar::SeekBar,
+    version,
+};
+
+use crate::{error, exit, logging, util};
 
 use crossbeam_channel::{select, tick};
 
+use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
 use thiserror::Error;
@@ -20,6 +32,16 @@ pub enum Error {
 
     #[error(transparent)]
     KodiControlError(#[from] kodi_control::Error),
+
+    #[error(transparent)]
+    ConfigError(#[from] config::Error),
+}
+
+// Settings for the UI, mostly from the config file
+pub struct Options {
+    pub keymap: keymap::Keymap,
+    pub layout: config::Layout,
+    pub theme: config::Theme,
 }
 
 pub struct Ui {
@@ -30,7 +52,18 @@ pub struct Ui {
 struct UiData {
     kodi_control: Arc<Mutex<KodiControl>>,
     exit: exit::Exit,
+    keymap: keymap::Keymap,
+    layout: config::Layout,
+    // whether the compact layout is currently shown
+    compact: bool,
     last_known_seconds: u32,
+    last_known_total_seconds: u32,
+    chapters: kodi_control::Chapters,
+    // A-B loop markers, in seconds
+    loop_a: Option<u32>,
+    loop_b: Option<u32>,
+    // the console's scrollback, kept while it's closed
+    console: VecDeque<String>,
 }
 
 #[derive(Debug)]
@@ -107,6 +140,156 @@ fn playlist_next(siv: &mut Cursive) {
     with_kodi(siv, Some("playlist_next"), |kc| kc.playlist_next());
 }
 
+fn seek_chapter(siv: &mut Cursive, index: usize) {
+    let ui_data: &UiData = siv.user_data().unwrap();
+    match ui_data.chapters.starts.get(index).cloned() {
+        None => (),
+        Some(start) => {
+            let seek = kodi_rpc_types::Seek::AbsolutePercentage { percentage: start };
+            let info = with_kodi(siv, None, |kc| kc.seek(seek));
+            update_time_from_seek_info(siv, info);
+        }
+    }
+}
+
+fn next_chapter(siv: &mut Cursive) {
+    let ui_data: &UiData = siv.user_data().unwrap();
+    if ui_data.chapters.starts.is_empty() {
+        // Kodi knows the chapters even if it can't tell us
+        with_kodi(siv, None, |kc| {
+            kc.execute_action(kodi_api::InputAction::Chapterorbigstepforward)
+        });
+    } else {
+        // current is 1-based, so it's also the index of the next one
+        let index = ui_data.chapters.current as usize;
+        seek_chapter(siv, index);
+    }
+}
+
+fn prev_chapter(siv: &mut Cursive) {
+    let ui_data: &UiData = siv.user_data().unwrap();
+    if ui_data.chapters.starts.is_empty() {
+        with_kodi(siv, None, |kc| {
+            kc.execute_action(kodi_api::InputAction::Chapterorbigstepback)
+        });
+    } else {
+        let index = (ui_data.chapters.current as usize).max(2) - 2;
+        seek_chapter(siv, index);
+    }
+}
+
+fn show_chapters(siv: &mut Cursive) {
+    let ui_data: &UiData = siv.user_data().unwrap();
+    if ui_data.chapters.starts.is_empty() {
+        return;
+    }
+    let total_seconds = ui_data.last_known_total_seconds;
+    let mut select = SelectView::<usize>::new();
+    for (index, start) in ui_data.chapters.starts.iter().enumerate() {
+        let time =
+            kodi_rpc_types::GlobalTime::from_seconds((start / 100.0 * total_seconds as f64) as u32);
+        select.add_item(format!("{:3}  {}", index + 1, time), index);
+    }
+    if ui_data.chapters.current > 0 {
+        select.set_selection(ui_data.chapters.current as usize - 1);
+    }
+    select.set_on_submit(|siv, index: &usize| {
+        siv.pop_layer();
+        seek_chapter(siv, *index);
+    });
+    siv.add_layer(
+        Dialog::around(select.scrollable())
+            .title("Chapters")
+            .dismiss_button("Close"),
+    );
+}
+
+fn update_chapters(siv: &mut Cursive, chapters: kodi_control::Chapters) {
+    let starts = chapters.starts.clone();
+    let ui_data: &mut UiData = siv.user_data().unwrap();
+    ui_data.chapters = chapters;
+    siv.call_on_name("chapter_marks", |view: &mut ChapterMarks| {
+        view.set_starts(starts);
+    });
+}
+
+fn relative_seek(siv: &mut Cursive, seconds: i32) {
+    let seek = kodi_rpc_types::Seek::RelativeSeconds { seconds };
+    let info = with_kodi(siv, None, |kc| kc.seek(seek));
+    update_time_from_seek_info(siv, info);
+}
+
+fn cycle_repeat(siv: &mut Cursive) {
+    with_kodi(siv, None, |kc| {
+        kc.set_repeat(kodi_api::PlayerSetRepeatParamsRepeat::String(
+            kodi_api::PlayerSetRepeatParamsRepeatString::Cycle,
+        ))
+    });
+}
+
+fn toggle_shuffle(siv: &mut Cursive) {
+    with_kodi(siv, None, |kc| {
+        kc.set_shuffle(kodi_api::GlobalToggle::String(
+            kodi_api::GlobalToggleString::Toggle,
+        ))
+    });
+}
+
+fn update_ab_loop(siv: &mut Cursive) {
+    let ui_data: &UiData = siv.user_data().unwrap();
+    let content = match (ui_data.loop_a, ui_data.loop_b) {
+        (None, _) => String::from(""),
+        (Some(a), None) => format!("A-B: {} - ", kodi_rpc_types::GlobalTime::from_seconds(a)),
+        (Some(a), Some(b)) => format!(
+            "A-B: {} - {}",
+            kodi_rpc_types::GlobalTime::from_seconds(a),
+            kodi_rpc_types::GlobalTime::from_seconds(b)
+        ),
+    };
+    siv.call_on_name("ab_loop", |view: &mut TextView| {
+        view.set_content(content);
+    });
+}
+
+// the loop is within an item, so it's gone once another one starts
+fn clear_ab_loop(siv: &mut Cursive) {
+    let ui_data: &mut UiData = siv.user_data().unwrap();
+    ui_data.loop_a = None;
+    ui_data.loop_b = None;
+    update_ab_loop(siv);
+}
+
+// sets A, then B; a third press clears the loop
+fn set_ab_marker(siv: &mut Cursive) {
+    let ui_data: &mut UiData = siv.user_data().unwrap();
+    let seconds = ui_data.last_known_seconds;
+    match (ui_data.loop_a, ui_data.loop_b) {
+        (None, _) => ui_data.loop_a = Some(seconds),
+        (Some(a), None) if seconds > a => ui_data.loop_b = Some(seconds),
+        (Some(_), None) => (), // B must come after A
+        (Some(_), Some(_)) => {
+            ui_data.loop_a = None;
+            ui_data.loop_b = None;
+        }
+    }
+    update_ab_loop(siv);
+}
+
+fn check_ab_loop(siv: &mut Cursive) {
+    let ui_data: &UiData = siv.user_data().unwrap();
+    match (ui_data.loop_a, ui_data.loop_b) {
+        (Some(a), Some(b)) if ui_data.last_known_seconds >= b => {
+            log::debug!("A-B loop: seeking back to {}", a);
+            let seek = kodi_rpc_types::Seek::AbsoluteTime {
+                time: kodi_rpc_types::GlobalTime::from_seconds(a),
+            };
+            let info = with_kodi(siv, None, |kc| kc.seek(seek));
+            update_time_from_seek_info(siv, info);
+        }
+        _ => (),
+    }
+}
+
 impl std::fmt::Display for kodi_rpc_types::GlobalTime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}:{:02}:{:02}", self.hours, self.minutes, self.seconds)
@@ -123,8 +306,18 @@ fn update_time(
         None => (),
         Some(time) => {
             let ui_data: &mut UiData = siv.user_data().unwrap();
-            ui_data.last_known_seconds =
-                time.hours as u32 * 3600 + time.minutes as u32 * 60 + time.seconds as u32;
+            ui_data.last_known_seconds = time.as_seconds();
+        }
+    }
+    match &total_time {
+        None => (),
+        Some(total_time) => {
+            let total_seconds = total_time.as_seconds();
+            let ui_data: &mut UiData = siv.user_data().unwrap();
+            ui_data.last_known_total_seconds = total_seconds;
+            siv.call_on_name("progress", |view: &mut SeekBar| {
+                view.set_total_seconds(total_seconds);
+            });
         }
     }
     siv.call_on_name("kodi_time", |view: &mut TextView| {
@@ -137,24 +330,38 @@ fn update_time(
     match percentage {
         None => (),
         Some(percentage) => {
-            let _ = siv.call_on_name("progress", |view: &mut ProgressBar| {
-                view.set_value(percentage as usize);
+            let _ = siv.call_on_name("progress", |view: &mut SeekBar| {
+                view.set_percentage(percentage);
             });
         }
     }
 }
 
+fn update_modes(siv: &mut Cursive, repeat: kodi_rpc_types::PlayerRepeat, shuffled: bool) {
+    siv.call_on_name("kodi_modes", |view: &mut TextView| {
+        let repeat = match repeat {
+            kodi_rpc_types::PlayerRepeat::Off => "off",
+            kodi_rpc_types::PlayerRepeat::One => "one",
+            kodi_rpc_types::PlayerRepeat::All => "all",
+        };
+        let shuffled = if shuffled { "on" } else { "off" };
+        view.set_content(format!("Repeat: {}  Shuffle: {}", repeat, shuffled));
+    });
+}
+
 fn update_time_from_seek_info(siv: &mut Cursive, seek: kodi_rpc_types::PlayerSeekReturns) {
     update_time(siv, seek.time, seek.total_time, seek.percentage)
 }
 
 fn update_time_from_properties(siv: &mut Cursive, properties: kodi_rpc_types::PlayerPropertyValue) {
+    update_modes(siv, properties.repeat, properties.shuffled);
     update_time(
         siv,
         properties.time,
         properties.total_time,
         Some(properties.percentage),
-    )
+    );
+    check_ab_loop(siv);
 }
 
 fn enter_seek_digit(siv: &mut Cursive, digit: char) {
@@ -175,6 +382,348 @@ fn enter_seek_digit(siv: &mut Cursive, digit: char) {
     );
 }
 
+fn show_send_text(siv: &mut Cursive) {
+    let edit = EditView::new().on_submit(|siv, text| {
+        let text = String::from(text);
+        siv.pop_layer();
+        with_kodi(siv, None, |kc| kc.input_send_text(&text, true));
+    });
+    siv.add_layer(
+        Dialog::around(edit.min_width(30))
+            .title("Send text")
+            .dismiss_button("Cancel"),
+    );
+}
+
+// the key that opened passthrough also closes it
+fn show_passthrough(siv: &mut Cursive, key: keymap::Key) {
+    let cb_sink = siv.cb_sink().clone();
+    let passthrough = Passthrough::new().set_callback(move |input| {
+        let _ = cb_sink.send(Box::new(move |siv| match input {
+            PassthroughInput::Button(button) => with_kodi(siv, None, |kc| kc.input(button)),
+            PassthroughInput::Text => show_send_text(siv),
+        }));
+    });
+    siv.add_layer(
+        Dialog::around(passthrough)
+            .title("Passthrough")
+            .wrap_with(OnEventView::new)
+            .on_event(key_event(key), |siv| {
+                siv.pop_layer();
+            }),
+    );
+}
+
+// entries kept in the console's scrollback
+const CONSOLE_SCROLLBACK: usize = 500;
+
+fn console_print(siv: &mut Cursive, entry: String) {
+    let ui_data: &mut UiData = siv.user_data().unwrap();
+    ui_data.console.push_back(entry.clone());
+    if ui_data.console.len() > CONSOLE_SCROLLBACK {
+        ui_data.console.pop_front();
+    }
+    siv.call_on_name("console_log", |view: &mut TextView| {
+        view.append(format!("{}\n", entry));
+    });
+}
+
+// "Method" or "Method {json params}"
+fn console_call(siv: &mut Cursive, input: &str) {
+    let mut parts = input.trim().splitn(2, char::is_whitespace);
+    let method = String::from(parts.next().unwrap_or(""));
+    if method.is_empty() {
+        return;
+    }
+    console_print(siv, format!("> {}", input.trim()));
+    let params = match parts.next().map(str::trim).filter(|x| !x.is_empty()) {
+        None => None,
+        Some(params) => match serde_json::from_str(params) {
+            Ok(params) => Some(params),
+            Err(err) => {
+                console_print(siv, format!("Invalid parameters: {}", err));
+                return;
+            }
+        },
+    };
+    let result = with_kodi(siv, None, |kc| kc.rpc(&method, params));
+    console_print(
+        siv,
+        match result {
+            Ok(value) => serde_json::to_string_pretty(&value).unwrap_or_default(),
+            Err(err) => format!("Error: {}", err),
+        },
+    );
+}
+
+// raw JSON-RPC calls, with the notifications from Kodi in between
+fn show_console(siv: &mut Cursive) {
+    let ui_data: &UiData = siv.user_data().unwrap();
+    let mut scrollback = String::new();
+    for entry in &ui_data.console {
+        scrollback.push_str(entry);
+        scrollback.push('\n');
+    }
+    let edit = EditView::new().on_submit(|siv, input| {
+        let input = String::from(input);
+        siv.call_on_name("console_input", |view: &mut EditView| {
+            view.set_content("");
+        });
+        console_call(siv, &input);
+    });
+    siv.add_layer(
+        Dialog::around(
+            LinearLayout::vertical()
+                .child(
+                    TextView::new(scrollback)
+                        .with_name("console_log")
+                        .scrollable()
+                        .scroll_strategy(cursive::view::ScrollStrategy::StickToBottom)
+                        .min_height(10),
+                )
+                .child(DummyView)
+                .child(edit.with_name("console_input").min_width(60)),
+        )
+        .title("Console: Method {params}")
+        .dismiss_button("Close"),
+    );
+}
+
+fn show_help(siv: &mut Cursive) {
+    let ui_data: &UiData = siv.user_data().unwrap();
+    let help: Vec<String> = ui_data
+        .keymap
+        .bindings
+        .iter()
+        .map(|(key, action)| format!("{:>10}  {}", key.to_string(), action))
+        .collect();
+    siv.add_layer(
+        Dialog::around(TextView::new(help.join("\n")).scrollable())
+            .title("Keys")
+            .dismiss_button("Close"),
+    );
+}
+
+fn key_event(key: keymap::Key) -> cursive::event::Event {
+    use cursive::event::{Event, Key};
+    match key {
+        keymap::Key::Char(char) => Event::Char(char),
+        keymap::Key::Left => Event::Key(Key::Left),
+        keymap::Key::Right => Event::Key(Key::Right),
+        keymap::Key::Up => Event::Key(Key::Up),
+        keymap::Key::Down => Event::Key(Key::Down),
+        keymap::Key::PageUp => Event::Key(Key::PageUp),
+        keymap::Key::PageDown => Event::Key(Key::PageDown),
+        keymap::Key::Home => Event::Key(Key::Home),
+        keymap::Key::End => Event::Key(Key::End),
+        keymap::Key::Enter => Event::Key(Key::Enter),
+        keymap::Key::Esc => Event::Key(Key::Esc),
+        keymap::Key::Tab => Event::Key(Key::Tab),
+        keymap::Key::Backspace => Event::Key(Key::Backspace),
+        keymap::Key::Del => Event::Key(Key::Del),
+        keymap::Key::F(n) => Event::Key(match n {
+            1 => Key::F1,
+            2 => Key::F2,
+            3 => Key::F3,
+            4 => Key::F4,
+            5 => Key::F5,
+            6 => Key::F6,
+            7 => Key::F7,
+            8 => Key::F8,
+            9 => Key::F9,
+            10 => Key::F10,
+            11 => Key::F11,
+            _ => Key::F12,
+        }),
+    }
+}
+
+fn run_action(siv: &mut Cursive, key: keymap::Key, action: &keymap::Action) {
+    use keymap::Action;
+    use kodi_rpc_types::Step;
+    match action {
+        Action::Quit => siv.quit(),
+        Action::PlayPause => pause_play(siv),
+        Action::Next => playlist_next(siv),
+        Action::Previous => playlist_prev(siv),
+        Action::Step(Step::SmallBackward) => bwd_step_short(siv),
+        Action::Step(Step::BigBackward) => bwd_step_long(siv),
+        Action::Step(Step::SmallForward) => fwd_step_short(siv),
+        Action::Step(Step::BigForward) => fwd_step_long(siv),
+        Action::Seek(seconds) => relative_seek(siv, *seconds),
+        Action::SeekPrompt => {
+            // the keymap only allows this for digits and '-'
+            if let keymap::Key::Char(digit) = key {
+                enter_seek_digit(siv, digit)
+            }
+        }
+        Action::NextChapter => next_chapter(siv),
+        Action::PreviousChapter => prev_chapter(siv),
+        Action::Chapters => show_chapters(siv),
+        Action::Repeat => cycle_repeat(siv),
+        Action::Shuffle => toggle_shuffle(siv),
+        Action::AbLoop => set_ab_marker(siv),
+        Action::Passthrough => show_passthrough(siv, key),
+        Action::Console => show_console(siv),
+        Action::Help => show_help(siv),
+        Action::None => (),
+    }
+}
+
+// the smallest screen the full layout fits in; auto layout goes compact below this
+const FULL_LAYOUT_SIZE: (usize, usize) = (70, 14);
+
+// these are only updated when something changes, so they're carried over to a new layout
+const TEXT_VIEWS: &[&str] = &[
+    "kodi_playlist_position",
+    "kodi_time",
+    "kodi_modes",
+    "ab_loop",
+];
+
+fn wants_compact(layout: config::Layout, size: Vec2) -> bool {
+    match layout {
+        config::Layout::Auto => size.x < FULL_LAYOUT_SIZE.0 || size.y < FULL_LAYOUT_SIZE.1,
+        config::Layout::Full => false,
+        config::Layout::Compact => true,
+    }
+}
+
+fn main_padding(compact: bool) -> Margins {
+    if compact {
+        Margins::lrtb(1, 1, 0, 0)
+    } else {
+        Margins::lrtb(3, 3, 2, 2)
+    }
+}
+
+fn main_view(siv: &mut Cursive, compact: bool) -> Box<dyn View> {
+    let playlist_position = TextView::new("Waiting..").with_name("kodi_playlist_position");
+    let time = TextView::new("").with_name("kodi_time");
+    let modes = TextView::new("").with_name("kodi_modes");
+    let ab_loop = TextView::new("").with_name("ab_loop");
+
+    let progress = {
+        let cb_sink = siv.cb_sink().clone();
+        SeekBar::new()
+            .set_callback(move |percentage| {
+                let _ = cb_sink.send(Box::new(move |siv| {
+                    let seek = kodi_rpc_types::Seek::AbsolutePercentage { percentage };
+                    with_kodi(siv, None, |kc| kc.async_seek(seek));
+                }));
+            })
+            .with_name("progress")
+    };
+
+    let view = if compact {
+        // everything on one line; the progress bar gets what's left
+        let gap = || TextView::new("  ");
+        LinearLayout::horizontal()
+            .child(playlist_position)
+            .child(gap())
+            .child(time)
+            .child(gap())
+            .child(modes)
+            .child(gap())
+            .child(ab_loop)
+            .child(gap())
+            .child(progress)
+            .full_width()
+    } else {
+        // https://en.wikipedia.org/wiki/Media_control_symbols
+        let buttons = LinearLayout::horizontal()
+            .child(Button::new_raw("   \u{23ee}   ", playlist_prev).with_name("playlist_prev"))
+            .child(
+                Button::new_raw("   \u{23ea}\u{23ea}  ", bwd_step_long).with_name("bwd_step_long"),
+            )
+            .child(Button::new_raw("   \u{23ea}   ", bwd_step_short).with_name("bwd_step_short"))
+            .child(Button::new_raw("   \u{23ef}   ", pause_play).with_name("play_pause"))
+            .child(Button::new_raw("   \u{23e9}   ", fwd_step_short).with_name("fwd_step_short"))
+            .child(
+                Button::new_raw("   \u{23e9}\u{23e9}  ", fwd_step_long).with_name("fwd_step_long"),
+            )
+            .child(Button::new_raw("   \u{23ed}   ", playlist_next).with_name("playlist_next"))
+            .child(DummyView)
+            .child(Button::new_raw("Quit", quit));
+
+        LinearLayout::vertical()
+            .child(progress)
+            .child(ChapterMarks::new().with_name("chapter_marks"))
+            .child(playlist_position)
+            .child(time)
+            .child(modes)
+            .child(ab_loop)
+            .child(DummyView)
+            .child(buttons)
+            .full_width()
+    };
+
+    // the focused view gets the keys first, so e.g. space presses the focused button, except
+    // for keys the views use that have been bound to something else in the config, so that the
+    // seek bar doesn't eat a bound Left; quit is a global callback, see Ui::new
+    let defaults = keymap::Keymap::default().bindings;
+    let ui_data: &UiData = siv.user_data().unwrap();
+    let view = ui_data
+        .keymap
+        .bindings
+        .iter()
+        .filter(|(_, action)| *action != keymap::Action::Quit)
+        .cloned()
+        .fold(view.wrap_with(OnEventView::new), |view, (key, action)| {
+            let rebound = key.used_by_views() && !defaults.contains(&(key, action.clone()));
+            let callback = move |siv: &mut Cursive| run_action(siv, key, &action);
+            if rebound {
+                view.on_pre_event(key_event(key), callback)
+            } else {
+                view.on_event(key_event(key), callback)
+            }
+        });
+    Box::new(view)
+}
+
+// switches between the full and the compact layout when the screen size calls for it
+fn apply_layout(siv: &mut Cursive) {
+    let size = siv.screen_size();
+    if size.x == 0 || size.y == 0 {
+        return; // not drawn yet
+    }
+    let ui_data: &UiData = siv.user_data().unwrap();
+    let compact = wants_compact(ui_data.layout, size);
+    if compact == ui_data.compact {
+        return;
+    }
+    log::debug!(
+        "Switching to {} layout",
+        if compact { "compact" } else { "full" }
+    );
+    let texts: Vec<(&str, String)> = TEXT_VIEWS
+        .iter()
+        .filter_map(|name| {
+            siv.call_on_name(name, |view: &mut TextView| {
+                (*name, String::from(view.get_content().source()))
+            })
+        })
+        .collect();
+    let view = main_view(siv, compact);
+    siv.call_on_name("main", |dialog: &mut Dialog| {
+        dialog.set_content(view);
+        dialog.set_padding(main_padding(compact));
+    });
+    for (name, text) in texts {
+        siv.call_on_name(name, |view: &mut TextView| view.set_content(text));
+    }
+    let ui_data: &mut UiData = siv.user_data().unwrap();
+    ui_data.compact = compact;
+    let total_seconds = ui_data.last_known_total_seconds;
+    let starts = ui_data.chapters.starts.clone();
+    siv.call_on_name("progress", |view: &mut SeekBar| {
+        view.set_total_seconds(total_seconds);
+    });
+    siv.call_on_name("chapter_marks", |view: &mut ChapterMarks| {
+        view.set_starts(starts);
+    });
+}
+
 #[derive(Debug)]
 struct KodiInfoCallback {
     cb_sink: crossbeam_channel::Sender<Box<dyn FnOnce(&mut Cursive) + 'static + Send>>,
@@ -189,16 +738,52 @@ impl kodi_control::KodiInfoCallback for KodiInfoCallback {
                     None => String::from(""),
                 });
             });
+            clear_ab_loop(siv);
+        })) {
+            Ok(()) => (),
+            Err(_) => (), // ignore. maybe ui exited.
+        }
+    }
+
+    fn limitations(&mut self, limitations: Vec<String>) {
+        if limitations.is_empty() {
+            return;
+        }
+        match self.cb_sink.send(Box::new(move |siv| {
+            let text: Vec<String> = limitations.iter().map(|x| format!("- {}", x)).collect();
+            siv.add_layer(
+                Dialog::around(TextView::new(text.join("\n")).scrollable())
+                    .title("Limitations of this Kodi")
+                    .dismiss_button("Close"),
+            );
         })) {
             Ok(()) => (),
             Err(_) => (), // ignore. maybe ui exited.
         }
     }
+
+    fn notification(&mut self, notification: &serde_json::Value) {
+        let entry = format!(
+            "< {}",
+            serde_json::to_string_pretty(notification).unwrap_or_default()
+        );
+        match self
+            .cb_sink
+            .send(Box::new(move |siv| console_print(siv, entry)))
+        {
+            Ok(()) => (),
+            Err(_) => (), // ignore. maybe ui exited.
+        }
+    }
 }
 
 impl Ui {
-    pub fn new(kodi_control: KodiControl, exit: exit::Exit) -> Result<Ui, Error> {
+    pub fn new(kodi_control: KodiControl, exit: exit::Exit, options: Options) -> Result<Ui, Error> {
         let mut siv = Cursive::default();
+        siv.set_theme(Self::create_theme(
+            &options.theme,
+            siv.current_theme().clone(),
+        )?);
         let kodi_control = Arc::new(Mutex::new(kodi_control));
         kodi_control
             .lock()
@@ -206,75 +791,38 @@ impl Ui {
             .set_callback(Box::new(KodiInfoCallback {
                 cb_sink: siv.cb_sink().clone(),
             }))?;
+        // the screen size isn't known yet, so auto starts out full and is adjusted
+        // once drawn
+        let compact = options.layout == config::Layout::Compact;
         let ui_data = UiData {
             kodi_control: kodi_control.clone(),
             exit: exit.clone(),
+            keymap: options.keymap.clone(),
+            layout: options.layout,
+            compact,
             last_known_seconds: 0,
+            last_known_total_seconds: 0,
+            chapters: kodi_control::Chapters::default(),
+            loop_a: None,
+            loop_b: None,
+            console: VecDeque::new(),
         };
         siv.set_user_data(ui_data);
-        siv.set_theme(Self::create_theme(siv.current_theme().clone()));
-
-        let playlist_position = TextView::new("Waiting..").with_name("kodi_playlist_position");
-        let time = TextView::new("").with_name("kodi_time");
-
-        let progress = ProgressBar::new()
-            .range(0, 100)
-            .with_label(|_value: usize, _bounds: (usize, usize)| -> String { String::from("") })
-            .with_name("progress");
-
-        // https://en.wikipedia.org/wiki/Media_control_symbols
-        let buttons = LinearLayout::horizontal()
-            .child(Button::new_raw("   \u{23ee}   ", playlist_prev).with_name("playlist_prev"))
-            .child(
-                Button::new_raw("   \u{23ea}\u{23ea}  ", bwd_step_long).with_name("bwd_step_long"),
-            )
-            .child(Button::new_raw("   \u{23ea}   ", bwd_step_short).with_name("bwd_step_short"))
-            .child(Button::new_raw("   \u{23ef}   ", pause_play).with_name("play_pause"))
-            .child(Button::new_raw("   \u{23e9}   ", fwd_step_short).with_name("fwd_step_short"))
-            .child(
-                Button::new_raw("   \u{23e9}\u{23e9}  ", fwd_step_long).with_name("fwd_step_long"),
-            )
-            .child(Button::new_raw("   \u{23ed}   ", playlist_next).with_name("playlist_next"))
-            .child(DummyView)
-            .child(Button::new_raw("Quit", quit));
-
-        let view = LinearLayout::vertical()
-            .child(progress)
-            .child(DummyView)
-            .child(playlist_position)
-            .child(time)
-            .child(DummyView)
-            .child(buttons)
-            .full_width()
-            .wrap_with(OnEventView::new)
-            .on_event('<', bwd_step_long)
-            .on_event(',', bwd_step_short)
-            .on_event('.', fwd_step_short)
-            .on_event('>', fwd_step_long)
-            .on_event(
-                cursive::event::Event::Key(cursive::event::Key::PageUp),
-                playlist_prev,
-            )
-            .on_event('[', playlist_prev)
-            .on_event(
-                cursive::event::Event::Key(cursive::event::Key::PageDown),
-                playlist_next,
-            )
-            .on_event(']', playlist_next)
-            .on_event(' ', pause_play);
-
-        let view = "-0123456789".chars().fold(view, |view, digit| {
-            view.on_event(digit, move |siv: &mut Cursive| {
-                enter_seek_digit(siv, digit);
-            })
-        });
 
-        siv.add_global_callback('q', |s| s.quit());
+        // quitting works even with dialogs open
+        for (key, action) in &options.keymap.bindings {
+            if *action == keymap::Action::Quit {
+                siv.add_global_callback(key_event(*key), |s| s.quit());
+            }
+        }
+        siv.add_global_callback(cursive::event::Event::WindowResize, apply_layout);
 
+        let view = main_view(&mut siv, compact);
         siv.add_layer(
-            Dialog::around(LinearLayout::horizontal().child(view).full_width())
+            Dialog::around(view)
                 .title(format!("KodiKontrol {}", version::get_version()))
-                .padding(Margins::lrtb(3, 3, 2, 2)),
+                .padding(main_padding(compact))
+                .with_name("main"),
         );
 
         let polling_thread = {
@@ -296,19 +844,25 @@ impl Ui {
     ) {
         enum Event {
             Tick,
+            ChaptersTick,
         }
         let exit = exit.crossbeam_subscribe();
         let ticker = tick(std::time::Duration::from_millis(200));
+        // chapters only change along with the item, so they're polled less often
+        let chapters_ticker = tick(std::time::Duration::from_secs(2));
 
         log::debug!("Starting polling");
 
         while let Some(event) = select! {
             recv(exit) -> _ => None,
             recv(ticker) -> _ => Some(Event::Tick),
+            recv(chapters_ticker) -> _ => Some(Event::ChaptersTick),
         } {
             match event {
                 Event::Tick => {
-                    log::debug!("Tick");
+                    log::debug!(target: logging::POLL_TARGET, "Tick");
+                    // resize events don't cover the initial size
+                    let _ = cb_sink.send(Box::new(apply_layout));
                     let kodi_control = kodi_control.clone();
                     let cb_sink = cb_sink.clone();
                     let doit = move || -> Result<(), error::Error> {
@@ -317,6 +871,8 @@ impl Ui {
                             kodi_rpc_types::PlayerPropertyName::Percentage,
                             kodi_rpc_types::PlayerPropertyName::Time,
                             kodi_rpc_types::PlayerPropertyName::Speed,
+                            kodi_rpc_types::PlayerPropertyName::Repeat,
+                            kodi_rpc_types::PlayerPropertyName::Shuffled,
                         ])?;
                         match info {
                             Some(info) => cb_sink
@@ -328,8 +884,25 @@ impl Ui {
                         Ok(())
                     };
                     match doit() {
-                        Ok(()) => log::debug!("Cool"),
-                        Err(err) => log::debug!("error: {}", err),
+                        Ok(()) => log::debug!(target: logging::POLL_TARGET, "Cool"),
+                        Err(err) => log::debug!(target: logging::POLL_TARGET, "error: {}", err),
+                    }
+                }
+                Event::ChaptersTick => {
+                    let kodi_control = kodi_control.clone();
+                    let cb_sink = cb_sink.clone();
+                    let doit = move || -> Result<(), error::Error> {
+                        match kodi_control.lock().unwrap().chapters()? {
+                            Some(chapters) => cb_sink
+                                .send(Box::new(|s| update_chapters(s, chapters)))
+                                .map_err(|err| Error::CrossbeamSendError(err.to_string()))?,
+                            None => (),
+                        }
+                        Ok(())
+                    };
+                    match doit() {
+                        Ok(()) => (),
+                        Err(err) => log::debug!(target: logging::POLL_TARGET, "error: {}", err),
                     }
                 }
             }
@@ -337,18 +910,46 @@ impl Ui {
         log::debug!("Stopped polling");
     }
 
-    fn create_theme(mut theme: cursive::theme::Theme) -> cursive::theme::Theme {
+    fn create_theme(
+        options: &config::Theme,
+        mut theme: cursive::theme::Theme,
+    ) -> Result<cursive::theme::Theme, Error> {
         use cursive::theme;
         use cursive::theme::{BaseColor::*, Color::*, PaletteColor::*};
 
-        theme.shadow = false;
-        theme.borders = theme::BorderStyle::Simple;
-        theme.palette[Background] = Dark(Black);
-        theme.palette[View] = Light(Black);
-        theme.palette[Primary] = Light(White);
-        theme.palette[TitlePrimary] = Light(White);
+        match options.load_file()? {
+            Some(file_theme) => {
+                theme = file_theme;
+            }
+            None => {
+                theme.shadow = false;
+                theme.borders = theme::BorderStyle::Simple;
+                match options.base.unwrap_or_default() {
+                    config::ThemeBase::Dark => {
+                        theme.palette[Background] = Dark(Black);
+                        theme.palette[View] = Light(Black);
+                        theme.palette[Primary] = Light(White);
+                        theme.palette[TitlePrimary] = Light(White);
+                    }
+                    config::ThemeBase::Light => {
+                        theme.palette[Background] = Dark(White);
+                        theme.palette[View] = Light(White);
+                        theme.palette[Primary] = Dark(Black);
+                        theme.palette[Secondary] = Dark(Blue);
+                        theme.palette[TitlePrimary] = Dark(Blue);
+                        theme.palette[Highlight] = Dark(Blue);
+                        theme.palette[HighlightInactive] = Light(Blue);
+                        theme.palette[HighlightText] = Light(White);
+                    }
+                }
+            }
+        }
+
+        for (role, color) in options.palette_colors()? {
+            theme.palette[role] = color;
+        }
 
-        theme
+        Ok(theme)
     }
 
     pub fn control(&mut self) -> Control {
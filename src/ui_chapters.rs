@@ -0,0 +1,38 @@
+use crate::ui_seekbar::column_of;
+
+use cursive::{Printer, Vec2, View};
+
+// Ticks showing where the chapters start, drawn under the progress bar
+pub struct ChapterMarks {
+    // percentages
+    starts: Vec<f64>,
+}
+
+impl ChapterMarks {
+    pub fn new() -> ChapterMarks {
+        ChapterMarks { starts: Vec::new() }
+    }
+
+    pub fn set_starts(&mut self, starts: Vec<f64>) {
+        self.starts = starts;
+    }
+}
+
+impl View for ChapterMarks {
+    fn draw(&self, printer: &Printer<'_, '_>) {
+        let width = printer.size.x;
+        if width == 0 {
+            return;
+        }
+        for start in &self.starts {
+            // the first chapter starts at 0, which isn't worth a mark
+            if *start > 0.0 {
+                printer.print((column_of(*start, width), 0), "^");
+            }
+        }
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        Vec2::new(constraint.x, 1)
+    }
+}
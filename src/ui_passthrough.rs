@@ -0,0 +1,87 @@
+use cursive::{
+    event::{Event, EventResult, Key},
+    Printer, Vec2, View,
+};
+
+use crate::kodi_rpc_types::InputButton;
+
+pub enum PassthroughInput {
+    Button(InputButton),
+    // the user wants to type text for Kodi's keyboard dialog
+    Text,
+}
+
+const HELP: &[&str] = &[
+    "Keys are sent to Kodi",
+    "",
+    "arrows     navigate",
+    "enter      select",
+    "backspace  back",
+    "c          context menu",
+    "i          info",
+    "o          OSD",
+    "t          send text",
+    "esc        leave passthrough",
+];
+
+// Forwards navigation keys to Kodi, for menus, dialogs and the OSD
+pub struct Passthrough {
+    callback: Option<Box<dyn Fn(PassthroughInput)>>,
+}
+
+impl Passthrough {
+    pub fn new() -> Passthrough {
+        Passthrough { callback: None }
+    }
+
+    pub fn set_callback<F>(mut self, callback: F) -> Passthrough
+    where
+        F: Fn(PassthroughInput) + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    fn send(&self, input: PassthroughInput) -> EventResult {
+        if let Some(callback) = &self.callback {
+            callback(input);
+        }
+        EventResult::Consumed(None)
+    }
+}
+
+impl View for Passthrough {
+    fn draw(&self, printer: &Printer<'_, '_>) {
+        for (y, line) in HELP.iter().enumerate() {
+            printer.print((0, y), line);
+        }
+    }
+
+    fn required_size(&mut self, _constraint: Vec2) -> Vec2 {
+        let width = HELP.iter().map(|line| line.len()).max().unwrap_or(0);
+        Vec2::new(width, HELP.len())
+    }
+
+    fn take_focus(&mut self, _source: cursive::direction::Direction) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Key(Key::Up) => self.send(PassthroughInput::Button(InputButton::Up)),
+            Event::Key(Key::Down) => self.send(PassthroughInput::Button(InputButton::Down)),
+            Event::Key(Key::Left) => self.send(PassthroughInput::Button(InputButton::Left)),
+            Event::Key(Key::Right) => self.send(PassthroughInput::Button(InputButton::Right)),
+            Event::Key(Key::Enter) => self.send(PassthroughInput::Button(InputButton::Select)),
+            Event::Key(Key::Backspace) => self.send(PassthroughInput::Button(InputButton::Back)),
+            Event::Char('c') => self.send(PassthroughInput::Button(InputButton::ContextMenu)),
+            Event::Char('i') => self.send(PassthroughInput::Button(InputButton::Info)),
+            Event::Char('o') => self.send(PassthroughInput::Button(InputButton::ShowOSD)),
+            Event::Char('t') => self.send(PassthroughInput::Text),
+            Event::Key(Key::Esc) => EventResult::with_cb(|siv| {
+                siv.pop_layer();
+            }),
+            _ => EventResult::Ignored,
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use cursive::{
+    event::{Event, EventResult, Key, MouseButton, MouseEvent},
+    theme::ColorStyle,
+    Printer, Vec2, View,
+};
+
+use crate::kodi_rpc_types::GlobalTime;
+
+// how far Left/Right move the cursor, in percentages
+const KEY_STEP: f64 = 1.0;
+
+// A progress bar that can also be used for seeking: click or drag with the mouse, or
+// move the cursor with Left/Right and confirm with Enter
+pub struct SeekBar {
+    percentage: f64,
+    total_seconds: u32,
+    // the position being chosen but not yet seeked to
+    target: Option<f64>,
+    width: usize,
+    callback: Option<Box<dyn Fn(f64)>>,
+}
+
+impl SeekBar {
+    pub fn new() -> SeekBar {
+        SeekBar {
+            percentage: 0.0,
+            total_seconds: 0,
+            target: None,
+            width: 0,
+            callback: None,
+        }
+    }
+
+    pub fn set_callback<F>(mut self, callback: F) -> SeekBar
+    where
+        F: Fn(f64) + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    pub fn set_percentage(&mut self, percentage: f64) {
+        self.percentage = percentage;
+    }
+
+    pub fn set_total_seconds(&mut self, total_seconds: u32) {
+        self.total_seconds = total_seconds;
+    }
+
+    fn percentage_at(&self, x: usize) -> f64 {
+        if self.width <= 1 {
+            0.0
+        } else {
+            (x.min(self.width - 1) as f64 / (self.width - 1) as f64 * 100.0).clamp(0.0, 100.0)
+        }
+    }
+
+    fn time_at(&self, percentage: f64) -> GlobalTime {
+        GlobalTime::from_seconds((percentage / 100.0 * self.total_seconds as f64) as u32)
+    }
+
+    fn confirm(&mut self) -> EventResult {
+        match self.target.take() {
+            None => EventResult::Ignored,
+            Some(target) => {
+                // show the new position right away; the next poll corrects it if needed
+                self.percentage = target;
+                if let Some(callback) = &self.callback {
+                    callback(target);
+                }
+                EventResult::Consumed(None)
+            }
+        }
+    }
+}
+
+// drawing uses the printer's width, which need not be the one given to layout; the chapter
+// marks use the same columns
+pub(crate) fn column_of(percentage: f64, width: usize) -> usize {
+    if width <= 1 {
+        0
+    } else {
+        ((percentage / 100.0 * (width - 1) as f64).round() as usize).min(width - 1)
+    }
+}
+
+fn overlay(line: &mut [char], at: usize, text: &str) {
+    for (index, char) in text.chars().enumerate() {
+        if let Some(slot) = line.get_mut(at + index) {
+            *slot = char;
+        }
+    }
+}
+
+impl View for SeekBar {
+    fn draw(&self, printer: &Printer<'_, '_>) {
+        let width = printer.size.x;
+        if width == 0 {
+            return;
+        }
+        let mut line = vec![' '; width];
+        let shown = self.target.unwrap_or(self.percentage);
+        let elapsed = format!(" {} ", self.time_at(shown));
+        let remaining = format!(
+            " -{} ",
+            GlobalTime::from_seconds(
+                self.total_seconds
+                    .saturating_sub(self.time_at(shown).as_seconds())
+            )
+        );
+        overlay(&mut line, 0, &elapsed);
+        if width > elapsed.len() + remaining.len() {
+            overlay(&mut line, width - remaining.len(), &remaining);
+        }
+
+        let filled = column_of(self.percentage, width) + 1;
+        let filled = if self.percentage > 0.0 { filled } else { 0 };
+        let (done, rest): (String, String) = (
+            line[..filled].iter().collect(),
+            line[filled..].iter().collect(),
+        );
+        printer.with_color(ColorStyle::highlight(), |printer| {
+            printer.print((0, 0), &done);
+        });
+        printer.print((filled, 0), &rest);
+
+        if let Some(target) = self.target {
+            let x = column_of(target, width);
+            printer.with_color(ColorStyle::highlight_inactive(), |printer| {
+                printer.print((x, 0), &line[x].to_string());
+            });
+        }
+    }
+
+    fn layout(&mut self, size: Vec2) {
+        self.width = size.x;
+    }
+
+    fn required_size(&mut self, constraint: Vec2) -> Vec2 {
+        Vec2::new(constraint.x, 1)
+    }
+
+    fn take_focus(&mut self, _source: cursive::direction::Direction) -> bool {
+        true
+    }
+
+    fn on_event(&mut self, event: Event) -> EventResult {
+        match event {
+            Event::Mouse {
+                offset,
+                position,
+                event,
+            } => {
+                // while dragging the mouse may leave the bar, so clamp the position
+                let x = position.saturating_sub(offset).x;
+                match event {
+                    MouseEvent::Press(MouseButton::Left) => {
+                        if position.fits_in_rect(offset, (self.width, 1)) {
+                            self.target = Some(self.percentage_at(x));
+                            EventResult::Consumed(None)
+                        } else {
+                            EventResult::Ignored
+                        }
+                    }
+                    MouseEvent::Hold(MouseButton::Left) if self.target.is_some() => {
+                        self.target = Some(self.percentage_at(x));
+                        EventResult::Consumed(None)
+                    }
+                    MouseEvent::Release(MouseButton::Left) if self.target.is_some() => {
+                        self.target = Some(self.percentage_at(x));
+                        self.confirm()
+                    }
+                    _ => EventResult::Ignored,
+                }
+            }
+            Event::Key(Key::Left) => {
+                let target = self.target.unwrap_or(self.percentage) - KEY_STEP;
+                self.target = Some(target.max(0.0));
+                EventResult::Consumed(None)
+            }
+            Event::Key(Key::Right) => {
+                let target = self.target.unwrap_or(self.percentage) + KEY_STEP;
+                self.target = Some(target.min(100.0));
+                EventResult::Consumed(None)
+            }
+            Event::Key(Key::Enter) => self.confirm(),
+            Event::Key(Key::Esc) if self.target.is_some() => {
+                self.target = None;
+                EventResult::Consumed(None)
+            }
+            _ => EventResult::Ignored,
+        }
+    }
+}
@@ -0,0 +1,98 @@
+// New files in a directory, for koko watch. Only files that have been written and closed, or
+// moved in, count, so that a file still being copied isn't played half-way.
+#[cfg(target_os = "linux")]
+use futures::StreamExt;
+#[cfg(target_os = "linux")]
+use inotify::{EventMask, EventStream, Inotify, WatchMask};
+
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+
+    #[error("Watching a directory is only supported on Linux")]
+    UnsupportedError,
+}
+
+pub struct Watcher {
+    dir: PathBuf,
+    #[cfg(target_os = "linux")]
+    events: EventStream<Vec<u8>>,
+}
+
+impl Watcher {
+    #[cfg(target_os = "linux")]
+    pub fn new(dir: &Path) -> Result<Watcher, Error> {
+        let mut inotify = Inotify::init()?;
+        inotify.add_watch(dir, WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO)?;
+        let events = inotify.event_stream(vec![0u8; 4096])?;
+        Ok(Watcher {
+            dir: dir.to_path_buf(),
+            events,
+        })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(dir: &Path) -> Result<Watcher, Error> {
+        let _ = dir;
+        Err(Error::UnsupportedError)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // the next new file; None once the directory is gone
+    #[cfg(target_os = "linux")]
+    pub async fn next(&mut self) -> Option<Result<PathBuf, Error>> {
+        loop {
+            let event = match self.events.next().await? {
+                Ok(event) => event,
+                Err(err) => return Some(Err(err.into())),
+            };
+            if event.mask.contains(EventMask::IGNORED) {
+                return None;
+            }
+            let name = match event.name {
+                Some(name) => name,
+                None => continue,
+            };
+            // e.g. partial downloads
+            if name.to_string_lossy().starts_with('.') {
+                continue;
+            }
+            let path = self.dir.join(name);
+            if path.is_file() {
+                return Some(Ok(path));
+            }
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub async fn next(&mut self) -> Option<Result<PathBuf, Error>> {
+        None
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_watcher() {
+        let dir = std::env::temp_dir().join(format!("koko-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut watcher = Watcher::new(&dir).unwrap();
+        std::fs::write(dir.join(".a.mkv.part"), b"partial").unwrap();
+        std::fs::rename(dir.join(".a.mkv.part"), dir.join("a.mkv")).unwrap();
+        std::fs::write(dir.join("b.mkv"), b"whole").unwrap();
+        assert_eq!(watcher.next().await.unwrap().unwrap(), dir.join("a.mkv"));
+        assert_eq!(watcher.next().await.unwrap().unwrap(), dir.join("b.mkv"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(watcher.next().await.is_none());
+    }
+}
@@ -0,0 +1,131 @@
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::time::Duration;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("Invalid MAC address: {}", .0)]
+    InvalidMac(String),
+
+    #[error("Failed to send wake-up packet: {}", .0)]
+    SendError(#[from] std::io::Error),
+
+    #[error("Host {} did not wake up in {} seconds", .0, .1)]
+    TimeoutError(IpAddr, u64),
+}
+
+// the magic packet is resent this often while waiting, in case the first one got lost
+const RESEND_INTERVAL: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// aa:bb:cc:dd:ee:ff or aa-bb-cc-dd-ee-ff
+pub fn parse_mac(mac: &str) -> Result<[u8; 6], Error> {
+    let error = || Error::InvalidMac(String::from(mac));
+    let bytes = mac
+        .split(|char| char == ':' || char == '-')
+        .map(|byte| match byte.len() {
+            2 => u8::from_str_radix(byte, 16).map_err(|_| error()),
+            _ => Err(error()),
+        })
+        .collect::<Result<Vec<u8>, Error>>()?;
+    let mut result = [0u8; 6];
+    if bytes.len() != result.len() {
+        return Err(error());
+    }
+    result.copy_from_slice(&bytes);
+    Ok(result)
+}
+
+// six 0xff bytes followed by the MAC sixteen times
+pub fn magic_packet(mac: &[u8; 6]) -> Vec<u8> {
+    let mut packet = vec![0xffu8; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(mac);
+    }
+    packet
+}
+
+pub fn send_magic_packet(mac: &[u8; 6]) -> Result<(), Error> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&magic_packet(mac), "255.255.255.255:9")?;
+    Ok(())
+}
+
+async fn is_up(address: IpAddr, ports: &[u16]) -> bool {
+    for port in ports {
+        let connect = tokio::net::TcpStream::connect(SocketAddr::new(address, *port));
+        match tokio::time::timeout(POLL_INTERVAL, connect).await {
+            Ok(Ok(_)) => (),
+            _ => return false,
+        }
+    }
+    true
+}
+
+// Wakes the host up and waits until all the ports accept connections
+pub async fn wake(
+    address: IpAddr,
+    mac: &str,
+    ports: &[u16],
+    timeout: Duration,
+) -> Result<(), Error> {
+    let mac = parse_mac(mac)?;
+    let started = tokio::time::Instant::now();
+    let mut last_sent: Option<tokio::time::Instant> = None;
+    loop {
+        if is_up(address, ports).await {
+            log::info!("Host {} is up", address);
+            return Ok(());
+        }
+        if started.elapsed() >= timeout {
+            return Err(Error::TimeoutError(address, timeout.as_secs()));
+        }
+        let resend = match last_sent {
+            None => true,
+            Some(last_sent) => last_sent.elapsed() >= RESEND_INTERVAL,
+        };
+        if resend {
+            log::info!("Sending wake-up packet to {}", address);
+            send_magic_packet(&mac)?;
+            last_sent = Some(tokio::time::Instant::now());
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+// Waits until the ports accept connections too; false if they didn't in time
+pub async fn wait_for(address: IpAddr, ports: &[u16], timeout: Duration) -> bool {
+    let started = tokio::time::Instant::now();
+    loop {
+        if is_up(address, ports).await {
+            return true;
+        }
+        if started.elapsed() >= timeout {
+            return false;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magic_packet() {
+        let mac = parse_mac("00:11:22:aa:BB:ff").unwrap();
+        assert_eq!(mac, [0x00, 0x11, 0x22, 0xaa, 0xbb, 0xff]);
+        assert_eq!(parse_mac("00-11-22-aa-bb-ff").unwrap(), mac);
+        assert!(parse_mac("00:11:22:aa:bb").is_err());
+        assert!(parse_mac("00:11:22:aa:bb:fff").is_err());
+        assert!(parse_mac("00:11:22:aa:bb:gg").is_err());
+
+        let packet = magic_packet(&mac);
+        assert_eq!(packet.len(), 6 + 16 * 6);
+        assert_eq!(&packet[..6], &[0xff; 6]);
+        assert_eq!(&packet[6..12], &mac);
+        assert_eq!(&packet[96..], &mac);
+    }
+}
@@ -0,0 +1,83 @@
+// The WebSocket JSON-RPC server shared by fake_kodi and mock_kodi: requests are answered by
+// a Handler, and the notifications it queues for a connection are sent before the next answer.
+use async_tungstenite::tungstenite::{self, Message};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use serde_json::Value;
+use tokio::net::{TcpListener, TcpStream};
+
+use std::sync::Arc;
+
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error(transparent)]
+    IOError(#[from] std::io::Error),
+
+    #[error(transparent)]
+    WebSocketError(#[from] tungstenite::Error),
+
+    #[error(transparent)]
+    JsonDecodeError(#[from] serde_json::Error),
+}
+
+pub trait Handler: Send + Sync + 'static {
+    // the notifications for a new connection
+    fn connect(self: &Arc<Self>) -> mpsc::UnboundedReceiver<Value>;
+
+    // the response to a single JSON-RPC request
+    fn respond(self: &Arc<Self>, request: &Value) -> Value;
+}
+
+// accepts connections until the runtime shuts down
+pub async fn start<H: Handler>(
+    address: std::net::IpAddr,
+    port: u16,
+    handler: Arc<H>,
+) -> Result<std::net::SocketAddr, Error> {
+    let listener = TcpListener::bind((address, port)).await?;
+    let addr = listener.local_addr()?;
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    let handler = handler.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = serve(stream, handler).await {
+                            log::error!("WebSocket connection failed: {}", err);
+                        }
+                    });
+                }
+                Err(err) => {
+                    log::error!("Failed to accept: {}", err);
+                    return;
+                }
+            }
+        }
+    });
+    Ok(addr)
+}
+
+async fn serve<H: Handler>(stream: TcpStream, handler: Arc<H>) -> Result<(), Error> {
+    let mut websocket = async_tungstenite::tokio::accept_async(stream).await?;
+    let mut notifications = handler.connect();
+    loop {
+        tokio::select! {
+            biased;
+            Some(notification) = notifications.next() => {
+                websocket.send(Message::Text(notification.to_string())).await?;
+            }
+            message = websocket.next() => {
+                let request: Value = match message {
+                    Some(Ok(Message::Text(text))) => serde_json::from_str(&text)?,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => return Err(err.into()),
+                };
+                let response = handler.respond(&request);
+                websocket.send(Message::Text(response.to_string())).await?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+// A whole streaming session against mock_kodi, from serving the files to the end of playback
+use kodi_kontrol::{
+    exit,
+    kodi_control::{self, KodiControl},
+    mock_kodi::{self, MockKodi},
+    server,
+};
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[actix_rt::test]
+async fn test_session() {
+    let kodi = MockKodi::start(mock_kodi::Options {
+        address: "127.0.0.1".parse().unwrap(),
+        http_port: 0,
+        ws_port: 0,
+        item_duration: Duration::from_secs(1),
+    })
+    .await
+    .expect("Failed to start mock Kodi");
+
+    let dir = std::env::temp_dir().join(format!("koko-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut files = HashMap::new();
+    let mut urls_order = HashMap::new();
+    for (order, name) in ["a.mkv", "b.mkv"].iter().enumerate() {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0u8; 1000]).unwrap();
+        files.insert(String::from(*name), path);
+        urls_order.insert(String::from(*name), order);
+    }
+    let app_data = server::make_app_data_holder(server::AppData {
+        kodi_address: "127.0.0.1".parse().unwrap(),
+        ip_access_control: true,
+        kodi_auth: None,
+        files,
+        urls_order,
+        urls_start: HashMap::new(),
+        background_audio: None,
+        previously_logged_file: None,
+    });
+
+    // rpc_handler stops early if this is gone
+    let (_kodi_control, kodi_control_rx) = KodiControl::new();
+    let (session_tx, session_rx) = tokio::sync::oneshot::channel();
+    let session = server::Session::new(
+        app_data,
+        kodi.http_addr().port(),
+        kodi.ws_addr().port(),
+        0,
+        session_tx,
+        exit::Exit::new(),
+        kodi_control::Args::new(kodi_control_rx, kodi_control::Mode::Play),
+    );
+    let finished = async {
+        let session = session_rx.await.expect("No session");
+        session.finish().await
+    };
+    let (served, finished) = tokio::time::timeout(
+        Duration::from_secs(30),
+        futures::future::join(session, finished),
+    )
+    .await
+    .expect("The session didn't finish");
+    served.expect("Serving failed");
+    finished.expect("The session failed");
+
+    let fetched = kodi.fetched();
+    assert_eq!(fetched.len(), 2, "fetched: {:?}", fetched);
+    assert!(fetched[0].ends_with("/file/a.mkv"));
+    assert!(fetched[1].ends_with("/file/b.mkv"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
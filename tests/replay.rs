@@ -0,0 +1,155 @@
+// rpc_handler against recorded sessions replayed by fake_kodi
+use kodi_kontrol::{
+    config, exit,
+    fake_kodi::FakeKodi,
+    kodi_control::{self, KodiControl},
+    kodi_rpc, kodi_rpc_types, recording,
+};
+
+use futures::channel::mpsc;
+use url::Url;
+
+use std::time::Duration;
+
+fn load(name: &str) -> Vec<recording::Entry> {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/recordings")
+        .join(name);
+    recording::load(&path).expect("Failed to load recording")
+}
+
+fn args(
+    kodi_control_rx: kodi_control::KodiControlReceiver,
+    mode: kodi_control::Mode,
+    on_end: config::OnEnd,
+) -> kodi_control::Args {
+    kodi_control::Args {
+        on_end,
+        ..kodi_control::Args::new(kodi_control_rx, mode)
+    }
+}
+
+struct Handler {
+    sigint_tx: mpsc::Sender<()>,
+    done_rx: tokio::sync::oneshot::Receiver<Result<(), kodi_kontrol::error::Error>>,
+    // rpc_handler stops once this is gone
+    kodi_control: KodiControl,
+}
+
+async fn start(
+    kodi: &FakeKodi,
+    urls: Vec<Url>,
+    mode: kodi_control::Mode,
+    on_end: config::OnEnd,
+) -> Handler {
+    let session = kodi_rpc::connect(&kodi.url())
+        .await
+        .expect("Failed to connect to fake Kodi");
+    let (kodi_control, kodi_control_rx) = KodiControl::new();
+    let (sigint_tx, sigint_rx) = mpsc::channel(1);
+    let (stop_tx, _stop_rx) = tokio::sync::oneshot::channel();
+    let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+    tokio::spawn(kodi_control::rpc_handler(
+        session,
+        urls,
+        sigint_rx,
+        stop_tx,
+        done_tx,
+        exit::Exit::new(),
+        args(kodi_control_rx, mode, on_end),
+    ));
+    Handler {
+        sigint_tx,
+        done_rx,
+        kodi_control,
+    }
+}
+
+async fn finish(kodi: &FakeKodi, handler: Handler) {
+    let Handler { done_rx, .. } = handler;
+    tokio::time::timeout(Duration::from_secs(10), done_rx)
+        .await
+        .expect("rpc_handler didn't finish")
+        .expect("rpc_handler is gone")
+        .expect("rpc_handler failed");
+    assert_eq!(kodi.unmatched(), Vec::<String>::new());
+    assert_eq!(kodi.remaining(), Vec::<String>::new());
+}
+
+fn url(name: &str) -> Url {
+    Url::parse(&format!("http://127.0.0.1:8080/file/{}", name)).unwrap()
+}
+
+#[tokio::test]
+async fn test_play_single() {
+    let kodi = FakeKodi::start(load("play_single.jsonl")).await.unwrap();
+    let handler = start(
+        &kodi,
+        vec![url("a.mkv")],
+        kodi_control::Mode::Play,
+        config::OnEnd::Stop,
+    )
+    .await;
+    finish(&kodi, handler).await;
+}
+
+// the first item stopping while the next one is loading isn't the end
+#[tokio::test]
+async fn test_play_playlist() {
+    let kodi = FakeKodi::start(load("play_playlist.jsonl")).await.unwrap();
+    let handler = start(
+        &kodi,
+        vec![url("a.mkv"), url("b.mkv")],
+        kodi_control::Mode::Play,
+        config::OnEnd::StopAndHome,
+    )
+    .await;
+    finish(&kodi, handler).await;
+}
+
+// what the UI does, until ctrl-c
+#[tokio::test]
+async fn test_remote_control() {
+    let kodi = FakeKodi::start(load("remote.jsonl")).await.unwrap();
+    let handler = start(
+        &kodi,
+        vec![],
+        kodi_control::Mode::Remote,
+        config::OnEnd::LeavePlaying,
+    )
+    .await;
+    let Handler {
+        mut sigint_tx,
+        done_rx,
+        kodi_control,
+    } = handler;
+    // the requests wait for rpc_handler, like the UI thread does
+    let (kodi_control, properties) = tokio::task::spawn_blocking(move || {
+        let mut kodi_control = kodi_control;
+        kodi_control.play_pause().unwrap();
+        let properties = kodi_control
+            .properties(vec![
+                kodi_rpc_types::PlayerPropertyName::Time,
+                kodi_rpc_types::PlayerPropertyName::TotalTime,
+            ])
+            .unwrap();
+        (kodi_control, properties)
+    })
+    .await
+    .unwrap();
+    let properties = properties.expect("No properties");
+    assert_eq!(
+        properties.time.map(|time| time.as_milliseconds()),
+        Some(62000)
+    );
+    sigint_tx.try_send(()).unwrap();
+    finish(
+        &kodi,
+        Handler {
+            sigint_tx,
+            done_rx,
+            kodi_control,
+        },
+    )
+    .await;
+}